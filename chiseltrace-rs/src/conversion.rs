@@ -1,250 +1,530 @@
-use core::slice;
-use std::{cell::RefCell, collections::{BTreeMap, HashMap, HashSet}, rc::Rc};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, rc::Rc};
 use itertools::Itertools;
-use crate::{graphbuilder::DynPDGNode, pdg_spec::{ExportablePDG, ExportablePDGEdge, ExportablePDGNode, PDGSpecEdgeKind, PDGSpecNodeKind}};
+use anyhow::Result;
+use crate::{errors::Error, graph::{AdjacencyPDG, DisjointSet}, graphbuilder::{DynPDGNode, INITIAL_STATE_TIMESTAMP}, predicates::reconstruct_predicates, pdg_spec::{ExportablePDG, ExportablePDGEdge, ExportablePDGNode, FirrtlProvenance, PDGSpecEdgeKind, PDGSpecNodeKind}, util::read_source_files};
 
 /// Converts a (D)PDG in FIRRTL representation to Chisel representation based on FIRRTL source locator info.
+/// This runs the default conversion pipeline, see [`PassManager::with_default_passes`], with the source files that the PDG refers to.
 pub fn pdg_convert_to_source(pdg: ExportablePDG, verbose_name: bool, is_dpdg: bool) -> ExportablePDG {
-    // NOTE: This function operates on a two-list representation of the PDG. It might be worthwhile to switch to a linked
-    // memory representation in the future, as many of the operations performed here would be easier. Even just a simple
-    // adjecency list representation would probably be a lot better than what it is now.
-
-
-    // Here, we convert the PDG from FIRRTL representation to source representation.
-    // The only source information that is available is the source file and line mapping (char index *may* be used, but also results in
-    // more erroneous groups)
-    // Based on this, we can group nodes that belong to the same source statement. One issue is that
-    // multiple source statements may exist on the same line. This is not yet addressed by this tool.
-    // For example, also signals of type Bundle have the same source mapping to the definition of the entire bundle.
-    // This will cause them to get grouped, which may not be desired.
-
-    // First step is to make groups of vertices.
-    let mut grouped_nodes: HashMap<(String, u32, i64), Vec<(ExportablePDGNode, usize)>> = HashMap::new();
-    for (i, node) in pdg.vertices.iter().enumerate() {
-        // This correction is needed to counteract the correction in the graphbuilder.
-        // Basically, registers update, then the wires update. That means that a register update at t=x 
-        // cannot have wire dependencies at t=x, they must be earlier. Therefore, a correction was introduced.
-        // However, due to this correction, grouping was no longer working properly, so here we are.
-        let group_timestamp = if node.clocked { node.timestamp } else { node.timestamp + 1 };
-        grouped_nodes.entry((node.file.clone(), node.line, group_timestamp)).or_default().push((node.clone(), i));
+    let source_files = read_source_files(pdg.vertices.iter().map(|v| v.file.as_str()));
+    PassManager::with_default_passes().run(pdg, &ConversionOptions { verbose_name, is_dpdg, source_files })
+}
+
+// Here, we convert the PDG from FIRRTL representation to source representation.
+// The only source information that is available is the source file and line mapping, optionally with a column range.
+// The start column alone is not used, as it results in more erroneous groups.
+// Based on this, we can group nodes that belong to the same source statement. One issue is that
+// multiple source statements may exist on the same line. If no column range is available, this is approximated
+// by splitting the nodes of a line on the signals that they assign to.
+// For example, also signals of type Bundle have the same source mapping to the definition of the entire bundle.
+// This will cause them to get grouped, which may not be desired.
+// The conversion is split up in passes that each perform one transformation on the graph. The order of the default passes matters:
+// index edges must be squashed before grouping, and the self dependencies must be detected before the groups are merged.
+
+/// The names of the passes that make up the default conversion pipeline, in order.
+pub const DEFAULT_PASSES: [&str; 7] = [
+    SquashIndexEdges::NAME,
+    GroupBySourceLocation::NAME,
+    DetectSelfDependencies::NAME,
+    MergeGroups::NAME,
+    DedupLookupTables::NAME,
+    MergeIndexGroups::NAME,
+    ReconstructPredicates::NAME,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct ConversionOptions {
+    /// Adds the timestamp and source location to the names of the Chisel statements
+    pub verbose_name: bool,
+    /// Whether the converted graph is a DPDG (or a static PDG)
+    pub is_dpdg: bool,
+    /// The lines of the Chisel source files, keyed by their path in the PDG. Used to reconstruct the predicates of
+    /// control flow statements, see [`crate::util::read_source_files`].
+    pub source_files: HashMap<String, Vec<String>>
+}
+
+/// The intermediate result of the conversion that is passed from pass to pass. Passes that change the vertices or edges
+/// of the graph return a fresh state, as the groups and self dependencies refer to the vertices of the old graph.
+#[derive(Debug, Clone)]
+pub struct ConversionState {
+    pub graph: AdjacencyPDG,
+    /// Groups of vertex indices that belong to the same source statement. Set by a grouping pass, consumed by [`MergeGroups`].
+    pub groups: Option<Vec<Vec<u32>>>,
+    /// Indices into `groups` of the groups that depend on themselves through a clocked cycle.
+    pub self_dependencies: Vec<u32>
+}
+
+impl ConversionState {
+    pub fn new(graph: AdjacencyPDG) -> Self {
+        ConversionState { graph, groups: None, self_dependencies: vec![] }
     }
+}
 
+/// A single transformation step of the FIRRTL to Chisel conversion.
+pub trait ConversionPass {
+    /// The name that is used to refer to the pass from the command line and the GUI.
+    fn name(&self) -> &'static str;
 
-    // TODO: the bug comes from the fact that the timestamp is saturated to 0, so the grouper tries to group the initial value wire
-    // with other t=1 nodes, but there is no vertex reachability on the same timestamp, so it doesn't group properly.
-    // The solution would be to switch to i64 for timestamps (bad idea), or deploy a hotfix that would scan for non-chisel nodes at group time 1,
-    // then put them on group time 0 (also not great)
+    fn run(&self, state: ConversionState, options: &ConversionOptions) -> ConversionState;
+}
 
-    // Performance optimization: Pre-compute mapping between edge.from and a list of edges.
-    // It used to be pdg.edges.iter().filter(|r_e| r_e.from == traversed_edge.to && r_e.kind == PDGSpecEdgeKind::Data).cloned()).
-    // This is wildly inefficient and causes O(N^2) complexity. By pre-computing the filter using a hashmap, we reduce to O(N)
+/// Runs a configurable sequence of conversion passes.
+pub struct PassManager {
+    passes: Vec<Box<dyn ConversionPass>>
+}
 
-    let mut edges_by_from: HashMap<u32, Vec<_>> = HashMap::new();
-    for edge in &pdg.edges {
-        edges_by_from.entry(edge.from).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
+impl PassManager {
+    /// Creates a pass manager without any passes.
+    pub fn empty() -> Self {
+        PassManager { passes: vec![] }
     }
 
-    // Redirect all Index edges. The probes will be removed in the next step
-    let new_edges = pdg.edges.iter().flat_map(|e| {
-        if e.kind == PDGSpecEdgeKind::Index {
-            // Replace this edge.
-            let mut stack = vec![e];
-            let mut replacement_edges= vec![];
-            while let Some(traversed_edge) = stack.pop() {
-                // We use a stack graph traversal because a probe node may itself have an index dependency. If that is the case,
-                // we need to squash them
-                let Some(r_e) = edges_by_from.get(&traversed_edge.to) else {
-                    continue;
-                };
-
-                replacement_edges.extend(r_e.iter().filter(|r_e| r_e.kind == PDGSpecEdgeKind::Data).map(|x| *x).cloned());
-                stack.extend(r_e.iter().filter(|r_e| r_e.kind == PDGSpecEdgeKind::Index));
-            }
-            for r_e in &mut replacement_edges {
-                r_e.from = e.from;
-                r_e.clocked = e.clocked;
-                r_e.kind = PDGSpecEdgeKind::Index;
-            }
-            // println!("{:#?}", replacement_edges);
-            replacement_edges
-        } else if !pdg.vertices[e.from as usize].name.starts_with("defnode_probe") { // Should definitely be replaced in the future
-            // Filter away edges that come from a probe node
-            vec![e.clone()]
-        } else { vec![] }
-    }).collect::<Vec<_>>();
-
+    /// Creates a pass manager with the standard conversion pipeline, see [`DEFAULT_PASSES`].
+    pub fn with_default_passes() -> Self {
+        PassManager { passes: DEFAULT_PASSES.iter().filter_map(|name| pass_by_name(name)).collect() }
+    }
 
-    // Same thing here: filtering explodes time complexity, replace with HashMap
-    let mut edges_by_to: HashMap<u32, Vec<_>> = HashMap::new();
-    edges_by_from.clear();
-    for edge in &new_edges {
-        edges_by_from.entry(edge.from).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
-        edges_by_to.entry(edge.to).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
-    }
-
-
-    // For every group, check vertex reachability within the group and split if necessary.
-    // This is required for split compound signals.
-    let groups = grouped_nodes.values().flat_map(|g| {
-        let mut grouped_nodes = BTreeMap::from_iter(g.iter().map(|n| (n.1, n.0.clone())));
-        let mut groups = vec![];
-        while let Some(current_node) = grouped_nodes.pop_first() {
-            let mut stack = vec![(current_node.1, current_node.0)];
-            let mut current_group = vec![];
-            while let Some((visited_node, visited_idx)) = stack.pop() {
-
-                // let to_add = new_edges.iter().filter(|e| 
-                //     (e.to == visited_idx as u32 || e.from == visited_idx as u32) && e.kind != PDGSpecEdgeKind::Index);
-                // let to_add = edges_by_from.get(&(visited_idx as u32)).into_iter().flatten().chain(
-                //     edges_by_to.get(&(visited_idx as u32)).into_iter().flatten()
-                // ).filter(|e| e.kind != PDGSpecEdgeKind::Index);
-
-                let to_add = edges_by_from
-                    .get(&(visited_idx as u32))
-                    .into_iter()
-                    .flatten()
-                    .chain(
-                        edges_by_to.get(&(visited_idx as u32))
-                        .into_iter()
-                        .flatten()
-                        .filter(|x| x.from != x.to) // Do not process self-referring edges twice
-                    )
-                    .filter(|e| e.kind != PDGSpecEdgeKind::Index);
-
-                for edge in to_add {
-                    if let Some(x) = grouped_nodes.remove(&(edge.from as usize)) {
-                        stack.push((x, edge.from as usize));
-                    }
-                    if let Some(x) = grouped_nodes.remove(&(edge.to as usize)) {
-                        stack.push((x, edge.to as usize));
-                    }
-                }
-                if !visited_node.name.starts_with("defnode_probe") {// Bad solution, should probably replace it with something better.
-                    current_group.push((visited_node, visited_idx));
+    /// Creates a pass manager from a list of pass names. If no list is given, the default passes are used.
+    /// Any pass in `disabled` is then removed from the pipeline.
+    pub fn from_config(passes: Option<&[String]>, disabled: &[String]) -> Result<Self> {
+        let mut manager = match passes {
+            Some(names) => {
+                let mut manager = PassManager::empty();
+                for name in names {
+                    manager.add_pass(pass_by_name(name).ok_or(Error::UnknownConversionPass(name.clone()))?);
                 }
+                manager
             }
-            if !current_group.is_empty() {
-                groups.push(current_group);
+            None => PassManager::with_default_passes()
+        };
+
+        for name in disabled {
+            if pass_by_name(name).is_none() {
+                anyhow::bail!(Error::UnknownConversionPass(name.clone()));
             }
+            manager.remove_pass(name);
         }
-        groups
-    }).collect::<Vec<_>>(); 
 
-    // Map the old vertex indices to the newly grouped ones.
-    let edgemap = groups.iter().enumerate().flat_map(|(new_i, g)| {
-        let own_indices = g.iter().map(|v| v.1); // Indices are guaranteed to be unique
-        own_indices.map(move |idx| (idx as u32, new_i as u32))
-    }).collect::<HashMap<_,_>>();
-
-    // Filters out any intra-group edges. There is one problem with this: there may actually be a clocked self dependency
-    // somewhere in the grouped nodes. We should check from each group and add it if needed.
-    // Also, we need to dedup the edges.
-    let outgoing_edges = new_edges.iter()
-        .map(|e| ExportablePDGEdge{from: edgemap[&e.from], to: edgemap[&e.to], ..e.clone()})
-        .filter(|e| {
-        // If both to and from point to the same group, remove the edge.
-        e.to != e.from
-    });
-
-    // This can only occur when the PDG being converted is a static PDG. The DPDG is a DAG, so this does not happen for dynamic cases
-    let self_dependencies = groups.iter().filter_map(|g| {
-        let own_index = g[0].1 as u32;
-        // Simple DFS to find if there is a clocked cycle in the group
-        let group_indices = g.iter().map(|n| n.1 as u32).unique().collect::<Vec<_>>();
-        let group_edges = new_edges
-            .iter()
-            .filter(|e| group_indices.contains(&e.from) && group_indices.contains(&e.to))
-            .unique_by(|e| (e.from, e.to))
-            .collect::<Vec<_>>();
-        
-        let mut cycle_found = false;
-        let mut visited = std::collections::HashSet::new();
-        
-        'outer: for &node_idx in &group_indices {
-            if visited.contains(&node_idx) {
-                continue;
+        Ok(manager)
+    }
+
+    /// Appends a pass to the end of the pipeline.
+    pub fn add_pass(&mut self, pass: Box<dyn ConversionPass>) {
+        self.passes.push(pass);
+    }
+
+    /// Inserts a pass at the given position in the pipeline.
+    pub fn insert_pass(&mut self, index: usize, pass: Box<dyn ConversionPass>) {
+        self.passes.insert(index, pass);
+    }
+
+    /// Removes all passes with the given name. Returns true if any pass was removed.
+    pub fn remove_pass(&mut self, name: &str) -> bool {
+        let len_before = self.passes.len();
+        self.passes.retain(|p| p.name() != name);
+        self.passes.len() != len_before
+    }
+
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn run(&self, mut pdg: ExportablePDG, options: &ConversionOptions) -> ExportablePDG {
+        // Every vertex starts out as its own FIRRTL statement. The passes that merge vertices combine their provenance.
+        for (idx, v) in pdg.vertices.iter_mut().enumerate() {
+            if v.provenance.is_empty() {
+                v.provenance.push(FirrtlProvenance { index: idx as u32, name: v.name.clone() });
             }
-            let mut dfs_stack = vec![(node_idx, false, vec![node_idx])]; // (current_node, clocked_found, visited)
-        
-            while let Some((current_el, clocked_found, path)) = dfs_stack.pop() {
-                if visited.contains(&current_el) {
-                    continue;
-                }
-                visited.insert(current_el);
-        
-                for edge in &group_edges {
-                    if edge.from == current_el {
-                        if path.contains(&edge.to) {
-                            // Cycle found
-                            if edge.clocked || clocked_found {
-                                cycle_found = true;
-                                break 'outer;
-                            }
-                        } else {
-                            dfs_stack.push((edge.to, edge.clocked || clocked_found, path.iter().cloned().chain(std::iter::once(edge.to)).collect()));
-                        }
+        }
+        let mut state = ConversionState::new(AdjacencyPDG::from(pdg));
+        for pass in &self.passes {
+            state = pass.run(state, options);
+        }
+
+        state.graph.into()
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager::with_default_passes()
+    }
+}
+
+/// Looks up one of the built-in passes by its name.
+pub fn pass_by_name(name: &str) -> Option<Box<dyn ConversionPass>> {
+    let pass: Box<dyn ConversionPass> = match name {
+        SquashIndexEdges::NAME => Box::new(SquashIndexEdges),
+        GroupBySourceLocation::NAME => Box::new(GroupBySourceLocation),
+        DetectSelfDependencies::NAME => Box::new(DetectSelfDependencies),
+        MergeGroups::NAME => Box::new(MergeGroups),
+        DedupLookupTables::NAME => Box::new(DedupLookupTables),
+        MergeIndexGroups::NAME => Box::new(MergeIndexGroups),
+        ReconstructPredicates::NAME => Box::new(ReconstructPredicates),
+        _ => return None
+    };
+    Some(pass)
+}
+
+/// Redirects all index edges past the probe nodes they point to, and removes the edges that come from probe nodes.
+pub struct SquashIndexEdges;
+
+impl SquashIndexEdges {
+    pub const NAME: &str = "squash-index-edges";
+}
+
+impl ConversionPass for SquashIndexEdges {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn run(&self, state: ConversionState, _options: &ConversionOptions) -> ConversionState {
+        ConversionState::new(squash_index_edges(state.graph))
+    }
+}
+
+/// Groups the vertices that belong to the same source statement.
+pub struct GroupBySourceLocation;
+
+impl GroupBySourceLocation {
+    pub const NAME: &str = "group-statements";
+}
+
+impl ConversionPass for GroupBySourceLocation {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn run(&self, state: ConversionState, _options: &ConversionOptions) -> ConversionState {
+        let groups = group_by_source_location(&state.graph);
+        ConversionState { groups: Some(groups), ..state }
+    }
+}
+
+/// Finds the groups that depend on themselves through a clocked cycle. Merging the groups removes the intra-group edges,
+/// so this information has to be collected beforehand.
+pub struct DetectSelfDependencies;
+
+impl DetectSelfDependencies {
+    pub const NAME: &str = "self-dependencies";
+}
+
+impl ConversionPass for DetectSelfDependencies {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn run(&self, state: ConversionState, options: &ConversionOptions) -> ConversionState {
+        // This can only occur when the PDG being converted is a static PDG. The DPDG is a DAG, so this does not happen for dynamic cases
+        if options.is_dpdg {
+            return state;
+        }
+        let Some(groups) = &state.groups else {
+            return state;
+        };
+        let self_dependencies = find_clocked_self_dependencies(&state.graph, &group_membership(&state.graph, groups));
+        ConversionState { self_dependencies, ..state }
+    }
+}
+
+/// Merges every group into a single Chisel-level vertex. Without a preceding grouping pass, every vertex is its own group.
+pub struct MergeGroups;
+
+impl MergeGroups {
+    pub const NAME: &str = "merge-groups";
+}
+
+impl ConversionPass for MergeGroups {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn run(&self, state: ConversionState, options: &ConversionOptions) -> ConversionState {
+        let groups = state.groups.unwrap_or_else(|| {
+            (0..state.graph.vertices.len() as u32).filter(|idx| state.graph.vertices[*idx as usize].kind != PDGSpecNodeKind::Probe).map(|idx| vec![idx]).collect()
+        });
+        let graph = merge_groups(&state.graph, &groups, &state.self_dependencies, options.verbose_name);
+        ConversionState::new(graph)
+    }
+}
+
+/// Merges duplicate anonymous vertices, such as the ones generated by lookup tables.
+pub struct DedupLookupTables;
+
+impl DedupLookupTables {
+    pub const NAME: &str = "dedup-lookup-tables";
+}
+
+impl ConversionPass for DedupLookupTables {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn run(&self, state: ConversionState, _options: &ConversionOptions) -> ConversionState {
+        ConversionState::new(dedup_lookup_tables(state.graph))
+    }
+}
+
+/// Merges vertices that are connected by an index edge and share a source location.
+pub struct MergeIndexGroups;
+
+impl MergeIndexGroups {
+    pub const NAME: &str = "merge-index-groups";
+}
+
+impl ConversionPass for MergeIndexGroups {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn run(&self, state: ConversionState, _options: &ConversionOptions) -> ConversionState {
+        ConversionState::new(merge_index_groups(state.graph))
+    }
+}
+
+/// Reconstructs the predicate expressions of control flow statements from the source code and their data dependencies.
+pub struct ReconstructPredicates;
+
+impl ReconstructPredicates {
+    pub const NAME: &str = "reconstruct-predicates";
+}
+
+impl ConversionPass for ReconstructPredicates {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn run(&self, state: ConversionState, options: &ConversionOptions) -> ConversionState {
+        ConversionState { graph: reconstruct_predicates(state.graph, &options.source_files), ..state }
+    }
+}
+
+/// Redirects all index edges past the probe nodes they point to, and removes the edges that come from probe nodes.
+fn squash_index_edges(graph: AdjacencyPDG) -> AdjacencyPDG {
+    let mut new_edges = vec![];
+    for e in &graph.edges {
+        if e.kind == PDGSpecEdgeKind::Index {
+            // Replace this edge. We use a stack graph traversal because a probe node may itself have an index dependency.
+            // If that is the case, we need to squash them
+            let mut stack = vec![e.to];
+            let mut visited = HashSet::from([e.to]);
+            while let Some(traversed) = stack.pop() {
+                for r_e in graph.outgoing(traversed) {
+                    match r_e.kind {
+                        PDGSpecEdgeKind::Data => new_edges.push(ExportablePDGEdge { from: e.from, to: r_e.to, kind: PDGSpecEdgeKind::Index, clocked: e.clocked }),
+                        PDGSpecEdgeKind::Index => if visited.insert(r_e.to) {
+                            stack.push(r_e.to);
+                        },
+                        _ => ()
                     }
                 }
             }
+        } else if graph.vertices[e.from as usize].kind != PDGSpecNodeKind::Probe {
+            // Filter away edges that come from a probe node
+            new_edges.push(e.clone());
         }
+    }
 
-        if cycle_found {
-            Some(ExportablePDGEdge {from: edgemap[&own_index], to: edgemap[&own_index], kind: PDGSpecEdgeKind::Data, clocked: true})
-        } else {
-            None
+    AdjacencyPDG::new(graph.vertices, new_edges)
+}
+
+/// The source location of a statement: its source range if the PDG provides one, otherwise the entire line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct StatementLocation<'a> {
+    file: &'a str,
+    line: u32,
+    range: Option<(u32, u32)>
+}
+
+impl<'a> StatementLocation<'a> {
+    fn of(node: &'a ExportablePDGNode) -> Self {
+        StatementLocation { file: node.file.as_str(), line: node.line, range: node.end_char.map(|end| (node.char, end)) }
+    }
+}
+
+/// The signal that a Chisel statement assigns to. Anonymous FIRRTL nodes do not count.
+fn statement_target(node: &ExportablePDGNode) -> Option<&str> {
+    node.assigns_to.as_deref().filter(|_| node.is_chisel_assignment)
+}
+
+/// Whether two vertices belong to the same source statement. This is the same criterion as used by [`group_by_source_location`]:
+/// without a column range, statements on the same line that assign to different signals are considered separate.
+fn same_statement(a: &ExportablePDGNode, b: &ExportablePDGNode) -> bool {
+    if StatementLocation::of(a) != StatementLocation::of(b) {
+        return false;
+    }
+    match (a.end_char, statement_target(a), statement_target(b)) {
+        (None, Some(a_target), Some(b_target)) => a_target == b_target,
+        _ => true
+    }
+}
+
+/// Makes groups of vertices that belong to the same source statement. Vertices are grouped if they share a source location
+/// and timestamp, and are reachable from each other within that group. Probe nodes are left out of the groups.
+fn group_by_source_location(graph: &AdjacencyPDG) -> Vec<Vec<u32>> {
+    // Nodes are grouped on their source range if the PDG provides one. Otherwise, the entire line is used.
+    let mut locations: HashMap<(StatementLocation, i64), u32> = HashMap::new();
+    let location_of = graph.vertices.iter().map(|node| {
+        // This correction is needed to counteract the correction in the graphbuilder.
+        // Basically, registers update, then the wires update. That means that a register update at t=x
+        // cannot have wire dependencies at t=x, they must be earlier. Therefore, a correction was introduced.
+        // However, due to this correction, grouping was no longer working properly, so here we are.
+        // The initial state is not corrected: it only holds the initial register values and the wires they are computed from.
+        let group_timestamp = if node.clocked || node.timestamp == INITIAL_STATE_TIMESTAMP { node.timestamp } else { node.timestamp + 1 };
+        let next_id = locations.len() as u32;
+        *locations.entry((StatementLocation::of(node), group_timestamp)).or_insert(next_id)
+    }).collect::<Vec<_>>();
+
+    // The signals that are assigned by the Chisel statements in every component.
+    let mut targets: HashMap<u32, HashSet<&str>> = HashMap::new();
+    for (idx, node) in graph.vertices.iter().enumerate() {
+        if let Some(target) = statement_target(node) {
+            targets.entry(idx as u32).or_default().insert(target);
         }
-    });
+    }
 
-    let new_verts = groups.iter().map(|g| {
-        let contains_data = g.iter().any(|(v,_)| v.kind == PDGSpecNodeKind::DataDefinition || v.kind == PDGSpecNodeKind::Connection);
-        let contains_cond = g.iter().any(|(v,_)| v.kind == PDGSpecNodeKind::ControlFlow);
-        let contains_io = g.iter().any(|(v,_)| v.kind == PDGSpecNodeKind::IO);
+    // For every location, check vertex reachability within the location and split if necessary.
+    // This is required for split compound signals. Index edges are not traversed.
+    let mut components = DisjointSet::new(graph.vertices.len());
+    for e in &graph.edges {
+        if e.kind == PDGSpecEdgeKind::Index || location_of[e.from as usize] != location_of[e.to as usize] {
+            continue;
+        }
+        let (from_root, to_root) = (components.find(e.from), components.find(e.to));
+        if from_root == to_root {
+            continue;
+        }
 
-        let primary_statement = g.iter().find(|n| n.0.is_chisel_assignment);
+        // Without a column range, multiple statements on one line (a := x; b := y) share a location.
+        // Components that assign to disjoint sets of signals are assumed to be separate statements.
+        if graph.vertices[e.from as usize].end_char.is_none()
+            && let (Some(from_targets), Some(to_targets)) = (targets.get(&from_root), targets.get(&to_root))
+            && from_targets.is_disjoint(to_targets) {
+            continue;
+        }
 
+        let root = components.union(from_root, to_root);
+        let merged_targets = [from_root, to_root].into_iter()
+            .filter_map(|r| targets.remove(&r))
+            .reduce(|mut acc, t| { acc.extend(t); acc });
+        if let Some(merged_targets) = merged_targets {
+            targets.insert(root, merged_targets);
+        }
+    }
 
-        let vert_kind = if let Some(stmt) = primary_statement {
-            stmt.0.kind
-        } else {
-            if contains_io {
-                PDGSpecNodeKind::IO
-            } else if contains_data {
-                PDGSpecNodeKind::Connection
-            } else if contains_cond {
-                PDGSpecNodeKind::ControlFlow
-            } else {
-                PDGSpecNodeKind::Definition
-            }  
-        };
-        let mut v0 = &g[0].0;
-        let filename = v0.file.split("/").last().unwrap();
-        let node_name = if let Some((stmt, _)) = primary_statement {
-            v0 = stmt;
-            if verbose_name {
-                format!("{} at t={} ({}:{})", stmt.name, stmt.timestamp, filename, stmt.line)
-            } else {
-                stmt.name.clone()
-            }
-        } else {
-            format!("{}:{}", filename , v0.line)
+    let mut groups: Vec<Vec<u32>> = vec![];
+    let mut component_to_group = HashMap::new();
+    for (idx, node) in graph.vertices.iter().enumerate() {
+        // Probe nodes still connect the vertices of a group, but do not become part of it
+        if node.kind == PDGSpecNodeKind::Probe {
+            continue;
+        }
+        let group_idx = *component_to_group.entry(components.find(idx as u32)).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[group_idx].push(idx as u32);
+    }
+
+    groups
+}
+
+/// Maps every vertex to the index of the group it is in.
+fn group_membership(graph: &AdjacencyPDG, groups: &[Vec<u32>]) -> Vec<Option<u32>> {
+    let mut group_of = vec![None; graph.vertices.len()];
+    for (group_idx, group) in groups.iter().enumerate() {
+        for idx in group {
+            group_of[*idx as usize] = Some(group_idx as u32);
+        }
+    }
+    group_of
+}
+
+/// Merges every group into a single vertex and redirects the edges to the merged vertices. Intra-group edges are removed.
+fn merge_groups(graph: &AdjacencyPDG, groups: &[Vec<u32>], self_dependencies: &[u32], verbose_name: bool) -> AdjacencyPDG {
+    // Map the old vertex indices to the newly grouped ones.
+    let group_of = group_membership(graph, groups);
+
+    let new_verts = groups.iter().map(|g| merge_group_vertices(graph, g, verbose_name)).collect::<Vec<_>>();
+
+    // Filters out any intra-group edges and edges to vertices that are not part of a group.
+    let mut new_edges = graph.edges.iter().filter_map(|e| {
+        let (Some(from), Some(to)) = (group_of[e.from as usize], group_of[e.to as usize]) else {
+            return None;
         };
-        ExportablePDGNode {name: node_name, kind: vert_kind, ..v0.clone()}
+        (from != to).then(|| ExportablePDGEdge { from, to, ..e.clone() })
     }).collect::<Vec<_>>();
 
-    let merged_edges = if is_dpdg {
-        outgoing_edges.map(|e| {
-            // Some edges may be unjustly marked as non-clocked. We need to restore them
-            ExportablePDGEdge { clocked: new_verts[e.from as usize].clocked, ..e }
-        })  
-        .unique().collect::<Vec<_>>()
+    // Removing the intra-group edges loses any clocked self dependency of a group, so these are restored.
+    new_edges.extend(self_dependencies.iter().map(|g| {
+        ExportablePDGEdge { from: *g, to: *g, kind: PDGSpecEdgeKind::Data, clocked: true }
+    }));
+
+    // Some edges may be unjustly marked as non-clocked. We need to restore them. Also, we need to dedup the edges.
+    let new_edges = new_edges.into_iter()
+        .map(|e| ExportablePDGEdge { clocked: new_verts[e.from as usize].clocked, ..e })
+        .unique()
+        .collect::<Vec<_>>();
+
+    AdjacencyPDG::new(new_verts, new_edges)
+}
+
+/// Returns the groups that contain a cycle with at least one clocked edge.
+fn find_clocked_self_dependencies(graph: &AdjacencyPDG, group_of: &[Option<u32>]) -> Vec<u32> {
+    let is_intra_group = |e: &ExportablePDGEdge| {
+        group_of[e.from as usize].is_some() && group_of[e.from as usize] == group_of[e.to as usize]
+    };
+    // A clocked edge is part of a cycle if both ends are in the same strongly connected component
+    let components = graph.strongly_connected_components(is_intra_group);
+    graph.edges.iter()
+        .filter(|e| e.clocked && is_intra_group(e) && components[e.from as usize] == components[e.to as usize])
+        .filter_map(|e| group_of[e.from as usize])
+        .unique()
+        .collect()
+}
+
+/// Creates the Chisel-level vertex for a group of FIRRTL vertices.
+fn merge_group_vertices(graph: &AdjacencyPDG, group: &[u32], verbose_name: bool) -> ExportablePDGNode {
+    let members = group.iter().map(|idx| &graph.vertices[*idx as usize]);
+    let contains_data = members.clone().any(|v| v.kind == PDGSpecNodeKind::DataDefinition || v.kind == PDGSpecNodeKind::Connection);
+    let contains_cond = members.clone().any(|v| v.kind == PDGSpecNodeKind::ControlFlow);
+    let contains_io = members.clone().any(|v| v.kind == PDGSpecNodeKind::IO);
+
+    let primary_statement = members.clone().find(|v| v.is_chisel_assignment);
+
+    let vert_kind = if let Some(stmt) = primary_statement {
+        stmt.kind
+    } else if contains_io {
+        PDGSpecNodeKind::IO
+    } else if contains_data {
+        PDGSpecNodeKind::Connection
+    } else if contains_cond {
+        PDGSpecNodeKind::ControlFlow
+    } else {
+        PDGSpecNodeKind::Definition
+    };
+
+    let mut v0 = &graph.vertices[group[0] as usize];
+    let filename = v0.file.split("/").last().unwrap();
+    let node_name = if let Some(stmt) = primary_statement {
+        v0 = stmt;
+        if verbose_name {
+            format!("{} at t={} ({}:{})", stmt.name, stmt.timestamp, filename, stmt.line)
+        } else {
+            stmt.name.clone()
+        }
     } else {
-        outgoing_edges.chain(self_dependencies).map(|e| {
-            // Some edges may be unjustly marked as non-clocked. We need to restore them
-            ExportablePDGEdge { clocked: new_verts[e.from as usize].clocked, ..e }
-        })  
-        .unique().collect::<Vec<_>>()
+        format!("{}:{}", filename, v0.line)
     };
+    let provenance = group.iter().flat_map(|idx| graph.vertices[*idx as usize].provenance.iter().cloned()).collect();
+    ExportablePDGNode { name: node_name, kind: vert_kind, provenance, ..v0.clone() }
+}
+
+/// Adds the provenance of the vertices that are merged away to the vertices they are merged into.
+/// `merged_into` may contain chains, in which case the provenance ends up at the end of the chain.
+fn transfer_provenance(graph: &mut AdjacencyPDG, merged_into: &HashMap<u32, u32>) {
+    for (removed, target) in merged_into.iter().sorted() {
+        let mut target = *target;
+        let mut visited = HashSet::from([*removed]);
+        while let Some(next) = merged_into.get(&target) {
+            if !visited.insert(target) {
+                break;
+            }
+            target = *next;
+        }
+        let provenance = graph.vertices[*removed as usize].provenance.clone();
+        graph.vertices[target as usize].provenance.extend(provenance);
+    }
+}
 
+/// Merges duplicate anonymous vertices that have the same dependencies.
+fn dedup_lookup_tables(mut graph: AdjacencyPDG) -> AdjacencyPDG {
     // There is another problem: some constructs, such as lookup tables may generate an enormous amount of nodes.
     // Most of these have been merged at this point, but there may still be some that are not. These nodes are
     // marked as non-chisel statements and can therefore not contain simulation data. It is best to merge them for clarity.
@@ -254,208 +534,128 @@ pub fn pdg_convert_to_source(pdg: ExportablePDG, verbose_name: bool, is_dpdg: bo
     // A note: this processing step can result in erroneous behaviour in designs where an anonymous statement is in multiple
     // data paths, such as an adder tree.
     // To specifically target lookup tables, the following heuristic is used: the merged nodes must have the same dependencies.
-
-    edges_by_to.clear();
-    edges_by_from.clear();
-    for edge in &merged_edges {
-        edges_by_from.entry(edge.from).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
-        edges_by_to.entry(edge.to).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
-    }
-
-    let mut removed_indices = vec![];
-    let mut processed_verts: HashMap<i64, Vec<(&ExportablePDGNode, usize)>> = HashMap::new();
-    for (vert_idx, vert) in new_verts.iter().enumerate() {
-        processed_verts.entry(vert.timestamp)
-            .and_modify(|vs| {
-                if let Some((_, dup_idx)) = vs.iter().find(|(v, _)| !v.is_chisel_assignment &&
-                v.file == vert.file && v.line == vert.line && v.name == vert.name) {
-                    // There is a duplicate vertex. Now we have to check if the edges are the same.
-                    // If so, we discard.
-                    let mut connected_edges_dup_from = HashSet::new();
-                    for check_edge in edges_by_from.get(&(*dup_idx as u32)).into_iter().flatten() {
-                        connected_edges_dup_from.insert(check_edge.to);
-                    }
-                    let mut connected_edges_dup_to = HashSet::new();
-                    for check_edge in edges_by_to.get(&(*dup_idx as u32)).into_iter().flatten() {
-                        connected_edges_dup_to.insert(check_edge.from);
-                    }
-
-                    let mut duplicate = true;
-                    for check_edge in edges_by_from.get(&(vert_idx as u32)).into_iter().flatten() {
-                        if !connected_edges_dup_from.contains(&check_edge.to) {
-                            duplicate = false;
-                            break;
-                        }
-                    }
-                    for check_edge in edges_by_to.get(&(vert_idx as u32)).into_iter().flatten() {
-                        if !connected_edges_dup_to.contains(&check_edge.from) {
-                            duplicate = false;
-                            break;
-                        }
-                    }
-
-                    if duplicate {
-                        removed_indices.push(vert_idx);
-                    }
-                } else {
-                    // If for the verts timestamp, we do not find a vert that is same, we add the vert to the processed verts.
-                    // If needed, we could check for the edges too (they will have the same ones), but I don't think it is necessary.
-                    vs.push((vert, vert_idx));
+    let mut removed = vec![false; graph.vertices.len()];
+    let mut merged_into = HashMap::new();
+    {
+        let mut first_seen: HashMap<(i64, StatementLocation, Option<&str>, &str), u32> = HashMap::new();
+        // The neighbourhoods of the vertices that are kept, so they are only computed once per duplicate set.
+        let mut neighbourhoods: HashMap<u32, (HashSet<u32>, HashSet<u32>)> = HashMap::new();
+        for (vert_idx, vert) in graph.vertices.iter().enumerate() {
+            let vert_idx = vert_idx as u32;
+            let key = (vert.timestamp, StatementLocation::of(vert), vert.assigns_to.as_deref(), vert.name.as_str());
+            if let Some(dup_idx) = first_seen.get(&key) {
+                // There is a duplicate vertex. Now we have to check if the edges are the same.
+                // If so, we discard.
+                let (dup_deps, dup_providers) = neighbourhoods.entry(*dup_idx).or_insert_with(|| {
+                    (graph.outgoing(*dup_idx).map(|e| e.to).collect(), graph.incoming(*dup_idx).map(|e| e.from).collect())
+                });
+                let duplicate = graph.outgoing(vert_idx).all(|e| dup_deps.contains(&e.to)) &&
+                    graph.incoming(vert_idx).all(|e| dup_providers.contains(&e.from));
+
+                if duplicate {
+                    removed[vert_idx as usize] = true;
+                    merged_into.insert(vert_idx, *dup_idx);
                 }
-            })
-            .or_insert(vec![(vert, vert_idx)]);
-    }
-
-    // Now we just delete the duplicate verts.
-    removed_indices.sort();
-    removed_indices.reverse();
-    let mut pruned_verts = new_verts.clone();
-    for i in &removed_indices {
-        pruned_verts.remove(*i);
-    }
-
-    // and update the edges as well
-    let mut edge_remap: HashMap<usize, Option<usize>> = HashMap::new();
-    let mut removed_counter = 0;
-    for i in 0..new_verts.len() {
-        edge_remap.insert(i,if removed_indices.contains(&i) {
-            removed_counter += 1;
-            None
-        } else {
-            Some(i - removed_counter)
-        });
+            } else if !vert.is_chisel_assignment {
+                first_seen.insert(key, vert_idx);
+            }
+        }
     }
 
-    let remapped_edges = merged_edges.iter().filter_map(|e| {
-        let remap_to = edge_remap[&(e.to as usize)];
-        let remap_from = edge_remap[&(e.from as usize)];
-
-        if let (Some(to), Some(from)) = (remap_to, remap_from) {
-            Some(ExportablePDGEdge{from: from as u32, to: to as u32, ..e.clone()})
-        } else {
-            None
-        }
-    }).collect::<Vec<_>>();
+    transfer_provenance(&mut graph, &merged_into);
+    graph.remove_vertices(&removed)
+}
 
-    // We add some more nodes to the delete list here. This has to do with the fact that during grouping, index edges are not traversed.
-    // This means that groups might be generated that should be grouped together.
-    // TODO: this goes for the one above as well. I really don't like the way the graph is being processed.
-    // This function should move to a different graph representation.
-
-    let mut removed_indices = vec![];
-    let index_edges = remapped_edges.iter().filter(|e| e.kind == PDGSpecEdgeKind::Index);
-    for edge in index_edges {
-        let from_node = &pruned_verts[edge.from as usize];
-        let to_node = &pruned_verts[edge.to as usize];
-        if from_node.file == to_node.file && from_node.line == to_node.line {
+/// Merges vertices that are connected by an index edge and belong to the same source statement.
+fn merge_index_groups(mut graph: AdjacencyPDG) -> AdjacencyPDG {
+    // During grouping, index edges are not traversed. This means that groups might be generated that should be grouped together.
+    let mut removed = vec![false; graph.vertices.len()];
+    let mut merged_into = HashMap::new();
+    for edge in graph.edges.iter().filter(|e| e.kind == PDGSpecEdgeKind::Index) {
+        let from_node = &graph.vertices[edge.from as usize];
+        let to_node = &graph.vertices[edge.to as usize];
+        if same_statement(from_node, to_node) {
             // These should have been merged. Mark to node for deletion
-            removed_indices.push(edge.to as usize);
+            removed[edge.to as usize] = true;
+            merged_into.entry(edge.to).or_insert(edge.from);
         }
     }
-
-    // Redirect the edges
-    edges_by_to.clear();
-    edges_by_from.clear();
-    for edge in &remapped_edges {
-        edges_by_from.entry(edge.from).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
-        edges_by_to.entry(edge.to).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
-    }
-    let redirected_edges = remapped_edges.iter().flat_map(|e| {
-        if removed_indices.contains(&(e.to as usize)) {
-            // Redirect this edge to the dependencies of the removed node
-            let redirected = edges_by_from.get(&e.to).into_iter().flatten()
-                .map(|e_re| ExportablePDGEdge {from: e.from, to: e_re.to, kind: PDGSpecEdgeKind::Index, clocked: e_re.clocked})
-                .collect::<Vec<_>>();
-            redirected
+    transfer_provenance(&mut graph, &merged_into);
+
+    // Redirect the edges to the dependencies of the removed nodes
+    let redirected_edges = graph.edges.iter().flat_map(|e| {
+        if removed[e.to as usize] {
+            graph.outgoing(e.to)
+                .map(|e_re| ExportablePDGEdge { from: e.from, to: e_re.to, kind: PDGSpecEdgeKind::Index, clocked: e_re.clocked })
+                .collect::<Vec<_>>()
         } else {
             vec![e.clone()]
         }
-    }).collect::<Vec<_>>();
+    }).unique().collect::<Vec<_>>();
 
-    removed_indices.sort();
-    removed_indices.reverse();
-    let mut new_pruned_verts = pruned_verts.clone();
-    for i in &removed_indices {
-        new_pruned_verts.remove(*i);
-    }
+    AdjacencyPDG::new(graph.vertices, redirected_edges).remove_vertices(&removed)
+}
 
-    let mut edge_remap: HashMap<usize, Option<usize>> = HashMap::new();
-    let mut removed_counter = 0;
-    for i in 0..pruned_verts.len() {
-        edge_remap.insert(i,if removed_indices.contains(&i) {
-            removed_counter += 1;
-            None
-        } else {
-            Some(i - removed_counter)
-        });
+/// Expands a converted (Chisel-level) vertex into the FIRRTL vertices it was created from. `firrtl` must be the graph
+/// that `pdg` was converted from. The first FIRRTL vertex takes the place of the expanded vertex, the others are appended,
+/// so the indices of all other vertices stay valid. The edges of the expanded vertex are replaced by the FIRRTL edges of its members.
+/// Returns the indices of the vertices that now represent the members.
+pub fn expand_node(pdg: &mut ExportablePDG, firrtl: &ExportablePDG, node: u32) -> Result<Vec<u32>> {
+    let Some(vert) = pdg.vertices.get(node as usize) else {
+        anyhow::bail!(Error::VertexNotFound(node));
+    };
+    let members = vert.provenance.iter().map(|p| p.index).collect::<Vec<_>>();
+    if members.is_empty() {
+        anyhow::bail!(Error::NoProvenance(node));
+    }
+    if let Some(missing) = members.iter().find(|m| **m as usize >= firrtl.vertices.len()) {
+        anyhow::bail!(Error::VertexNotFound(*missing));
     }
 
-    let remapped_edges = redirected_edges.iter().filter_map(|e| {
-        let remap_to = edge_remap[&(e.to as usize)];
-        let remap_from = edge_remap[&(e.from as usize)];
+    // Look up where every FIRRTL vertex ended up in the converted graph
+    let mut owner = HashMap::new();
+    for (idx, v) in pdg.vertices.iter().enumerate() {
+        if idx as u32 != node {
+            owner.extend(v.provenance.iter().map(|p| (p.index, idx as u32)));
+        }
+    }
 
-        if let (Some(to), Some(from)) = (remap_to, remap_from) {
-            Some(ExportablePDGEdge{from: from as u32, to: to as u32, ..e.clone()})
+    let mut new_indices = vec![];
+    for (i, member) in members.iter().enumerate() {
+        let member_vert = &firrtl.vertices[*member as usize];
+        let expanded = ExportablePDGNode { provenance: vec![FirrtlProvenance { index: *member, name: member_vert.name.clone() }], ..member_vert.clone() };
+        let idx = if i == 0 {
+            pdg.vertices[node as usize] = expanded;
+            node
         } else {
-            None
-        }
-    }).collect::<Vec<_>>();
+            pdg.vertices.push(expanded);
+            pdg.vertices.len() as u32 - 1
+        };
+        owner.insert(*member, idx);
+        new_indices.push(idx);
+    }
 
-    // Scan for conditional statements without proper name reconstruction
-    // This is a bit hacky and serves to rename conditionals that depend on a _T anonymous FIRRTL signal
-    // We probably want to do this during the actual name generation stage in Chisel instead.
-    edges_by_to.clear();
-    edges_by_from.clear();
-    for edge in &remapped_edges {
-        edges_by_from.entry(edge.from).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
-        edges_by_to.entry(edge.to).and_modify(|x| x.push(edge)).or_insert(vec![edge]);
-    }
-    let old_verts = new_pruned_verts.clone(); // Bad workaround the borrow checker, fix later
-
-    for (id, vert) in new_pruned_verts.iter_mut().enumerate() {
-        if vert.kind == PDGSpecNodeKind::ControlFlow {
-            if let Some(signal_path) = &vert.related_signal {
-                if let Some(pred_name) = signal_path.signal_path.split(".").last() {
-                    if pred_name.starts_with("pred__T") {
-                        // Now we want to rename the node, so we scan for data dependencies of this node.
-                        let mut data_deps = vec![];
-                        for edge in edges_by_from.get(&(id as u32)).into_iter().flatten() {
-                            if edge.kind == PDGSpecEdgeKind::Data {
-                                if let Some(data_signal) = &old_verts[edge.to as usize].related_signal {
-                                    if let Some(data_signal_name) = data_signal.signal_path.split(".").last() {
-                                        let mut full_name = data_signal_name.to_string();
-                                        if data_signal.field_path.len() > 0 {
-                                            full_name += ".";
-                                            full_name += &data_signal.field_path;
-                                        }
-                                        data_deps.push(full_name);
-                                    }
-                                } else {
-                                    data_deps.push("anonStmt".into());
-                                }
-                            }
-                        }
-                        // Now that we have the data dependencies, we can construct a new name.
-                        let mut new_name = String::from("cond_on_[");
-                        let deps_len = data_deps.len();
-                        for (i, dep) in data_deps.into_iter().enumerate() {
-                            new_name += &dep;
-                            if i != deps_len -1 {
-                                new_name += "_and_"
-                            }
-                        }
-                        new_name += "]";
-                        vert.name = new_name
-                    }
-                }
-            }
+    pdg.edges.retain(|e| e.from != node && e.to != node);
+    let mut existing_edges = pdg.edges.iter().cloned().collect::<HashSet<_>>();
+    let member_set = members.iter().collect::<HashSet<_>>();
+    for e in &firrtl.edges {
+        if !member_set.contains(&e.from) && !member_set.contains(&e.to) {
+            continue;
+        }
+        // Vertices that were removed during the conversion, such as probes, have no owner
+        let (Some(from), Some(to)) = (owner.get(&e.from), owner.get(&e.to)) else {
+            continue;
+        };
+        if from == to {
+            continue;
+        }
+        let edge = ExportablePDGEdge { from: *from, to: *to, ..e.clone() };
+        if existing_edges.insert(edge.clone()) {
+            pdg.edges.push(edge);
         }
     }
 
-    ExportablePDG {
-        vertices: new_pruned_verts,
-        edges: remapped_edges
-    }
+    Ok(new_indices)
 }
 
 /// A data structure that aids in converting linked graphs into 2 list representation
@@ -520,6 +720,7 @@ pub fn dpdg_make_exportable(root: Rc<RefCell<DynPDGNode>>) -> ExportablePDG {
         }
     }
 
+    // Probe nodes keep their kind, so that the conversion passes and front-ends can tell them apart from the source statements
     let pdg_verts = scanned_nodes.nodes.iter().map(|el| {
         let node = el.borrow();
         ExportablePDGNode { name: format!("{}", node.inner.name), timestamp: node.timestamp, ..(*node.inner).clone().into()}
@@ -529,4 +730,81 @@ pub fn dpdg_make_exportable(root: Rc<RefCell<DynPDGNode>>) -> ExportablePDG {
     pdg.edges = edges.into_iter().collect::<Vec<_>>();
 
     pdg
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(line: u32, name: &str, is_chisel_assignment: bool) -> ExportablePDGNode {
+        ExportablePDGNode { file: "Top.scala".into(), line, char: 0, end_char: None, name: name.into(), kind: PDGSpecNodeKind::Connection,
+            clocked: false, module_path: vec![], related_signal: None, assigns_to: None, predicate: None, provenance: vec![], sim_data: None,
+            sim_history: None, golden_mismatch: None, timestamp: 0, is_chisel_assignment }
+    }
+
+    #[test]
+    fn groups_are_dropped_when_a_pass_removes_vertices() {
+        // Two duplicate lookup table entries that are merged by the dedup pass, and a statement that uses both
+        let pdg = ExportablePDG {
+            vertices: vec![node(1, "_T", false), node(1, "_T", false), node(2, "out", true)],
+            edges: vec![
+                ExportablePDGEdge { from: 2, to: 0, kind: PDGSpecEdgeKind::Data, clocked: false },
+                ExportablePDGEdge { from: 2, to: 1, kind: PDGSpecEdgeKind::Data, clocked: false },
+            ]
+        };
+        let passes = ["group-statements", "dedup-lookup-tables", "merge-groups"].map(String::from);
+        let manager = PassManager::from_config(Some(&passes), &[]).unwrap();
+        let converted = manager.run(pdg, &ConversionOptions { verbose_name: false, is_dpdg: true, ..Default::default() });
+        assert_eq!(converted.vertices.len(), 2);
+        assert_eq!(converted.edges.len(), 1);
+    }
+
+    fn ranged(node: ExportablePDGNode, char: u32, end_char: u32) -> ExportablePDGNode {
+        ExportablePDGNode { char, end_char: Some(end_char), ..node }
+    }
+
+    fn run_passes(pdg: ExportablePDG, passes: &[&str]) -> ExportablePDG {
+        let passes = passes.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        PassManager::from_config(Some(&passes), &[]).unwrap().run(pdg, &ConversionOptions { is_dpdg: true, ..Default::default() })
+    }
+
+    #[test]
+    fn index_groups_of_different_statements_on_one_line_are_kept_apart() {
+        // a(i) := x; b := a(j), both on line 1 with their own column range
+        let pdg = ExportablePDG {
+            vertices: vec![ranged(node(1, "a", true), 0, 10), ranged(node(1, "b", true), 12, 20)],
+            edges: vec![ExportablePDGEdge { from: 1, to: 0, kind: PDGSpecEdgeKind::Index, clocked: false }]
+        };
+        assert_eq!(run_passes(pdg.clone(), &["merge-index-groups"]).vertices.len(), 2);
+
+        // Without a column range, the assigned signals tell the statements apart
+        let mut pdg = pdg;
+        pdg.vertices[0].assigns_to = Some("a".into());
+        pdg.vertices[1].assigns_to = Some("b".into());
+        for v in &mut pdg.vertices {
+            v.end_char = None;
+        }
+        assert_eq!(run_passes(pdg.clone(), &["merge-index-groups"]).vertices.len(), 2);
+
+        pdg.vertices[1].assigns_to = Some("a".into());
+        assert_eq!(run_passes(pdg, &["merge-index-groups"]).vertices.len(), 1);
+    }
+
+    #[test]
+    fn lookup_tables_of_different_statements_on_one_line_are_kept_apart() {
+        let pdg = ExportablePDG {
+            vertices: vec![ranged(node(1, "_T", false), 0, 10), ranged(node(1, "_T", false), 12, 20)],
+            edges: vec![]
+        };
+        assert_eq!(run_passes(pdg.clone(), &["dedup-lookup-tables"]).vertices.len(), 2);
+
+        let mut pdg = pdg;
+        pdg.vertices[1] = ranged(pdg.vertices[1].clone(), 0, 10);
+        assert_eq!(run_passes(pdg, &["dedup-lookup-tables"]).vertices.len(), 1);
+    }
+
+    #[test]
+    fn default_pass_manager_runs_the_standard_pipeline() {
+        assert_eq!(PassManager::default().pass_names(), DEFAULT_PASSES);
+        assert!(PassManager::empty().pass_names().is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use std::{collections::HashMap, fmt::Display};
+
+use serde::Serialize;
+
+use crate::{golden::same_bits, pdg_spec::{ExportablePDG, ExportablePDGNode, PDGSpecNodeKind}, value_format::ValueFormat};
+
+/// How an activation of a statement differs between a failing and a passing run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DivergenceKind {
+    /// A control flow statement that evaluated differently
+    ControlDecision { failing: String, passing: String },
+    /// A statement that was active in both runs, but whose signal had a different value
+    Value { failing: String, passing: String },
+    /// A statement that was only active in the failing run
+    ExtraActivation,
+    /// A statement that was only active in the passing run
+    MissingActivation
+}
+
+impl DivergenceKind {
+    /// Decisions come before the values and activations they cause
+    fn order(&self) -> u8 {
+        match self {
+            DivergenceKind::ControlDecision { .. } => 0,
+            DivergenceKind::Value { .. } => 1,
+            DivergenceKind::ExtraActivation => 2,
+            DivergenceKind::MissingActivation => 3
+        }
+    }
+}
+
+impl Display for DivergenceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dec = |bits: &str| ValueFormat::Dec.format_bits(bits);
+        match self {
+            DivergenceKind::ControlDecision { failing, passing } =>
+                write!(f, "control decision was {} instead of {}", dec(failing), dec(passing)),
+            DivergenceKind::Value { failing, passing } => write!(f, "value was {} instead of {}", dec(failing), dec(passing)),
+            DivergenceKind::ExtraActivation => write!(f, "only active in the failing run"),
+            DivergenceKind::MissingActivation => write!(f, "only active in the passing run")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    /// The node in the failing DPDG, unless the statement was only active in the passing run
+    pub failing: Option<usize>,
+    /// The node in the passing DPDG, unless the statement was only active in the failing run
+    pub passing: Option<usize>,
+    /// The cycle relative to the end of the trace, which is cycle 0
+    pub cycle: i64,
+    pub kind: DivergenceKind
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DpdgDiff {
+    /// The pairs of failing and passing nodes that are the same activation of a statement
+    pub matched: Vec<(usize, usize)>,
+    /// The differences between the runs, from the earliest to the latest. Only the cycles that both traces cover are compared.
+    pub divergences: Vec<Divergence>,
+    /// The nodes of the failing DPDG at cycles before the start of the passing trace
+    pub unaligned_failing: Vec<usize>,
+    /// The nodes of the passing DPDG at cycles before the start of the failing trace
+    pub unaligned_passing: Vec<usize>
+}
+
+impl DpdgDiff {
+    /// The point at which the runs first diverged
+    pub fn earliest(&self) -> Option<&Divergence> {
+        self.divergences.first()
+    }
+}
+
+/// Identifies an activation of a statement in either run: the statement, its module instance, and its cycle relative
+/// to the end of the trace. The traces of both runs end at the criterion, so they are aligned at their last cycle.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ActivationKey<'a> {
+    file: &'a str,
+    line: u32,
+    char: u32,
+    name: &'a str,
+    kind: PDGSpecNodeKind,
+    module_path: &'a [String],
+    cycle: i64
+}
+
+/// The first cycle of a DPDG, relative to the end of the trace
+fn first_cycle(pdg: &ExportablePDG) -> i64 {
+    let first = pdg.vertices.iter().map(|v| v.timestamp).min().unwrap_or_default();
+    let last = pdg.vertices.iter().map(|v| v.timestamp).max().unwrap_or_default();
+    first - last
+}
+
+/// The activations of a DPDG from the given cycle on, by their key. Activations with the same key are kept in the order
+/// of the DPDG. The nodes at earlier cycles are returned separately.
+fn activations(pdg: &ExportablePDG, from_cycle: i64) -> (HashMap<ActivationKey<'_>, Vec<usize>>, Vec<usize>) {
+    let last = pdg.vertices.iter().map(|v| v.timestamp).max().unwrap_or_default();
+    let mut activations: HashMap<_, Vec<usize>> = HashMap::new();
+    let mut unaligned = vec![];
+    for (idx, v) in pdg.vertices.iter().enumerate() {
+        let cycle = v.timestamp - last;
+        if cycle < from_cycle {
+            unaligned.push(idx);
+            continue;
+        }
+        let key = ActivationKey { file: &v.file, line: v.line, char: v.char, name: &v.name, kind: v.kind,
+            module_path: &v.module_path, cycle };
+        activations.entry(key).or_default().push(idx);
+    }
+    (activations, unaligned)
+}
+
+fn value_divergence(failing: &ExportablePDGNode, passing: &ExportablePDGNode) -> Option<DivergenceKind> {
+    let (failing_value, passing_value) = (&failing.sim_data.as_ref()?.bits, &passing.sim_data.as_ref()?.bits);
+    if same_bits(failing_value, passing_value) {
+        return None;
+    }
+    let (failing_value, passing_value) = (failing_value.clone(), passing_value.clone());
+    Some(if failing.kind == PDGSpecNodeKind::ControlFlow {
+        DivergenceKind::ControlDecision { failing: failing_value, passing: passing_value }
+    } else {
+        DivergenceKind::Value { failing: failing_value, passing: passing_value }
+    })
+}
+
+/// Aligns the DPDG of a failing run with the DPDG of a passing run, and finds where they differ. Both DPDGs have to
+/// be traced from the same criterion and be in the same representation. If one trace goes back further than the other,
+/// its earlier cycles cannot be compared, and their nodes are reported as unaligned.
+pub fn diff_dpdgs(failing: &ExportablePDG, passing: &ExportablePDG) -> DpdgDiff {
+    let shared_from = first_cycle(failing).max(first_cycle(passing));
+    let (failing_activations, unaligned_failing) = activations(failing, shared_from);
+    let (passing_activations, unaligned_passing) = activations(passing, shared_from);
+
+    let mut matched = vec![];
+    let mut divergences = vec![];
+    for (key, failing_nodes) in &failing_activations {
+        let passing_nodes = passing_activations.get(key).map(|n| n.as_slice()).unwrap_or_default();
+        for (f, p) in failing_nodes.iter().zip(passing_nodes) {
+            matched.push((*f, *p));
+            if let Some(kind) = value_divergence(&failing.vertices[*f], &passing.vertices[*p]) {
+                divergences.push(Divergence { failing: Some(*f), passing: Some(*p), cycle: key.cycle, kind });
+            }
+        }
+        for f in failing_nodes.iter().skip(passing_nodes.len()) {
+            divergences.push(Divergence { failing: Some(*f), passing: None, cycle: key.cycle, kind: DivergenceKind::ExtraActivation });
+        }
+    }
+    for (key, passing_nodes) in &passing_activations {
+        let n_failing = failing_activations.get(key).map_or(0, |n| n.len());
+        for p in passing_nodes.iter().skip(n_failing) {
+            divergences.push(Divergence { failing: None, passing: Some(*p), cycle: key.cycle, kind: DivergenceKind::MissingActivation });
+        }
+    }
+
+    matched.sort();
+    divergences.sort_by_key(|d| (d.cycle, d.kind.order(), d.failing, d.passing));
+    DpdgDiff { matched, divergences, unaligned_failing, unaligned_passing }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pdg_spec::{SimValue, SimValueKind};
+
+    use super::*;
+
+    fn node(line: u32, name: &str, kind: PDGSpecNodeKind, timestamp: i64, bits: &str) -> ExportablePDGNode {
+        let sim_data = SimValue { type_name: None, width: bits.len() as u32, bits: bits.into(), signed: false, kind: SimValueKind::Ground,
+            enum_variant: None, binary_point: None, fields: vec![], display: None };
+        ExportablePDGNode { file: "Top.scala".into(), line, char: 0, end_char: None, name: name.into(), kind, clocked: false,
+            module_path: vec![], related_signal: None, assigns_to: None, predicate: None, provenance: vec![], sim_data: Some(sim_data),
+            sim_history: None, golden_mismatch: None, timestamp, is_chisel_assignment: true }
+    }
+
+    fn graph(vertices: Vec<ExportablePDGNode>) -> ExportablePDG {
+        ExportablePDG { vertices, edges: vec![] }
+    }
+
+    #[test]
+    fn traces_are_aligned_at_their_last_cycle() {
+        // The same activations, but the failing run reached the criterion three cycles later
+        let failing = graph(vec![
+            node(1, "en", PDGSpecNodeKind::ControlFlow, 5, "1"),
+            node(2, "out", PDGSpecNodeKind::Connection, 6, "0011"),
+        ]);
+        let passing = graph(vec![
+            node(2, "out", PDGSpecNodeKind::Connection, 3, "0011"),
+            node(1, "en", PDGSpecNodeKind::ControlFlow, 2, "1"),
+        ]);
+        let diff = diff_dpdgs(&failing, &passing);
+        assert_eq!(diff.matched, vec![(0, 1), (1, 0)]);
+        assert!(diff.divergences.is_empty());
+        assert!(diff.earliest().is_none());
+    }
+
+    #[test]
+    fn the_earliest_divergence_comes_first() {
+        let failing = graph(vec![
+            node(1, "en", PDGSpecNodeKind::ControlFlow, 0, "0"),
+            node(2, "x", PDGSpecNodeKind::Connection, 0, "0001"),
+            node(3, "y", PDGSpecNodeKind::Connection, 1, "0010"),
+            node(4, "out", PDGSpecNodeKind::Connection, 2, "0100"),
+        ]);
+        let passing = graph(vec![
+            node(1, "en", PDGSpecNodeKind::ControlFlow, 0, "1"),
+            node(2, "x", PDGSpecNodeKind::Connection, 0, "0000"),
+            node(4, "out", PDGSpecNodeKind::Connection, 2, "0101"),
+        ]);
+        let diff = diff_dpdgs(&failing, &passing);
+        let kinds = diff.divergences.iter().map(|d| (d.cycle, d.kind.clone())).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![
+            // Within a cycle, the decision is listed before the value it may have caused
+            (-2, DivergenceKind::ControlDecision { failing: "0".into(), passing: "1".into() }),
+            (-2, DivergenceKind::Value { failing: "0001".into(), passing: "0000".into() }),
+            (-1, DivergenceKind::ExtraActivation),
+            (0, DivergenceKind::Value { failing: "0100".into(), passing: "0101".into() }),
+        ]);
+        let earliest = diff.earliest().unwrap();
+        assert_eq!((earliest.failing, earliest.passing), (Some(0), Some(0)));
+    }
+
+    #[test]
+    fn cycles_that_only_one_trace_covers_are_not_compared() {
+        let failing = graph(vec![
+            node(1, "a", PDGSpecNodeKind::Connection, 0, "1"),
+            node(2, "out", PDGSpecNodeKind::Connection, 1, "1"),
+            node(3, "b", PDGSpecNodeKind::Connection, 2, "1"),
+        ]);
+        let passing = graph(vec![
+            node(2, "out", PDGSpecNodeKind::Connection, 0, "1"),
+            node(4, "c", PDGSpecNodeKind::Connection, 0, "1"),
+            node(3, "b", PDGSpecNodeKind::Connection, 1, "1"),
+        ]);
+        let diff = diff_dpdgs(&failing, &passing);
+        assert_eq!(diff.unaligned_failing, vec![0]);
+        assert!(diff.unaligned_passing.is_empty());
+        assert_eq!(diff.matched, vec![(1, 0), (2, 2)]);
+        assert_eq!(diff.divergences.len(), 1);
+        assert_eq!((diff.divergences[0].passing, &diff.divergences[0].kind), (Some(1), &DivergenceKind::MissingActivation));
+    }
+}
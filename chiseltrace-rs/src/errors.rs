@@ -7,18 +7,30 @@ pub enum Error {
     StatementLookupError(String),
     #[error("Clock signal not found")]
     ClockNotFoundError,
+    #[error("Scope \"{0}\" not found in the VCD file")]
+    ScopeNotFound(String),
     #[error("Variable \"{0}\" not found")]
     VariableNotFoundError(String),
     #[error("HGLDD reader error: {0:?}")]
     HGLDDError(hgldd::reader::HglddReaderError),
     #[error("TyVCD builder error: {0:?}")]
     TyVCDBuilderError(tywaves_rs::tyvcd::builder::BuilderError),
+    #[error("TyVCD builder did not produce a model")]
+    TyVCDMissing,
     #[error("TyVCD rewriter error: {0:?}")]
     TyVCDRewriterError(tywaves_rs::vcd_rewrite::VcdRewriteError),
     #[error("Tywaves signal not found")]
     TywavesSignalNotFound,
     #[error("Tywaves variable downcast failed")]
-    TywavesDowncastFailed
+    TywavesDowncastFailed,
+    #[error("Unknown conversion pass \"{0}\"")]
+    UnknownConversionPass(String),
+    #[error("Vertex {0} does not exist")]
+    VertexNotFound(u32),
+    #[error("Vertex {0} was not created by the conversion to Chisel")]
+    NoProvenance(u32),
+    #[error("Invalid golden value file: {0}")]
+    GoldenValueParseError(String)
 }
 
 // Auto-implementation did not work
@@ -0,0 +1,441 @@
+use std::{collections::BTreeMap, fmt::Write as _, fs, path::Path};
+
+use anyhow::Result;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{pdg_spec::{ExportablePDG, ExportablePDGEdge, ExportablePDGNode, PDGSpecEdgeKind, PDGSpecNodeKind}, translation::{interpret_node_value, node_label}, value_format::{signal_key, FormatOverrides}};
+
+// The visual conventions of the viewer, which are reused by the exporters
+
+#[derive(Debug)]
+pub enum NodeColour {
+    Yellow,
+    Green,
+    Blue,
+    Red,
+    Grey
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeShape {
+    Ellipse,
+    Box,
+    Diamond,
+    Triangle
+}
+
+impl From<PDGSpecNodeKind> for NodeColour {
+    fn from(value: PDGSpecNodeKind) -> Self {
+        match value {
+            PDGSpecNodeKind::Connection => NodeColour::Blue,
+            PDGSpecNodeKind::ControlFlow => NodeColour::Red,
+            PDGSpecNodeKind::IO => NodeColour::Green,
+            PDGSpecNodeKind::DataDefinition => NodeColour::Blue,
+            PDGSpecNodeKind::Definition => NodeColour::Yellow,
+            PDGSpecNodeKind::Probe => NodeColour::Grey
+        }
+    }
+}
+
+impl NodeColour {
+    pub fn to_hex(&self) -> String {
+        match *self {
+            NodeColour::Blue => "#97C2FC",
+            NodeColour::Red => "#FB7E81",
+            NodeColour::Green => "#7BE141",
+            NodeColour::Yellow => "#FFFF00",
+            NodeColour::Grey => "#C0C0C0"
+        }.into()
+    }
+}
+
+
+impl Serialize for NodeColour {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl From<PDGSpecNodeKind> for NodeShape {
+    fn from(value: PDGSpecNodeKind) -> Self {
+        match value {
+            PDGSpecNodeKind::Connection => NodeShape::Ellipse,
+            PDGSpecNodeKind::ControlFlow => NodeShape::Diamond,
+            PDGSpecNodeKind::Probe => NodeShape::Triangle,
+            _ => NodeShape::Box
+        }
+    }
+}
+
+impl NodeShape {
+    /// The name of the shape, which is the same in vis.js and Graphviz
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeShape::Ellipse => "ellipse",
+            NodeShape::Box => "box",
+            NodeShape::Diamond => "diamond",
+            NodeShape::Triangle => "triangle"
+        }
+    }
+
+    /// The closest GEXF shape
+    fn gexf_name(&self) -> &'static str {
+        match self {
+            NodeShape::Ellipse => "disc",
+            NodeShape::Box => "square",
+            NodeShape::Diamond => "diamond",
+            NodeShape::Triangle => "triangle"
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EdgeColour {
+    Blue,
+    Red,
+    Purple
+}
+
+impl From<PDGSpecEdgeKind> for EdgeColour {
+    fn from(value: PDGSpecEdgeKind) -> Self {
+        match value {
+            PDGSpecEdgeKind::Data => EdgeColour::Blue,
+            PDGSpecEdgeKind::Conditional => EdgeColour::Red,
+            PDGSpecEdgeKind::Index => EdgeColour::Purple,
+            PDGSpecEdgeKind::Declaration => EdgeColour::Blue,
+        }
+    }
+}
+
+impl EdgeColour {
+    pub fn to_hex(&self) -> String {
+        match *self {
+            EdgeColour::Blue => "#97C2FC".into(),
+            EdgeColour::Red => "#FB7E81".into(),
+            EdgeColour::Purple => "#bc2dcc".into(),
+        }
+    }
+}
+
+
+impl Serialize for EdgeColour {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DotOptions {
+    /// Also clusters the nodes of each timestamp by the module instance they are in
+    pub cluster_modules: bool,
+    /// The display formats of the simulation values on the edges
+    pub formats: FormatOverrides
+}
+
+/// The nodes of one timestamp in a module instance, and the instances below it
+#[derive(Default)]
+struct ModuleCluster<'a> {
+    nodes: Vec<usize>,
+    children: BTreeMap<&'a str, ModuleCluster<'a>>
+}
+
+/// Renders a (D)PDG as a Graphviz digraph. The nodes are clustered by timestamp, and edges carry the value
+/// of the node they point to, like in the viewer.
+pub fn pdg_to_dot(pdg: &ExportablePDG, options: &DotOptions) -> String {
+    let mut timestamps: BTreeMap<i64, ModuleCluster> = BTreeMap::new();
+    for (idx, v) in pdg.vertices.iter().enumerate() {
+        let mut cluster = timestamps.entry(v.timestamp).or_default();
+        if options.cluster_modules {
+            for instance in v.module_path.iter().filter(|p| !p.is_empty()) {
+                cluster = cluster.children.entry(instance.as_str()).or_default();
+            }
+        }
+        cluster.nodes.push(idx);
+    }
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph dpdg {{").unwrap();
+    writeln!(dot, "  node [style=filled];").unwrap();
+
+    let mut cluster_count = 0;
+    for (timestamp, cluster) in &timestamps {
+        let label = format!("t = {}", timestamp);
+        write_cluster(&mut dot, pdg, options, &label, cluster, 1, &mut cluster_count);
+    }
+
+    for e in &pdg.edges {
+        let destination = &pdg.vertices[e.to as usize];
+        let label = interpret_node_value(destination, &options.formats)
+            .map(|t| t.label())
+            .unwrap_or_default();
+        let style = if e.clocked { ", style=dotted" } else { "" };
+        writeln!(dot, "  n{} -> n{} [color=\"{}\", label=\"{}\"{}];", e.from, e.to, EdgeColour::from(e.kind).to_hex(), escape(&label), style).unwrap();
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+pub fn write_dot<P: AsRef<Path>>(pdg: &ExportablePDG, options: &DotOptions, path: P) -> Result<()> {
+    fs::write(path, pdg_to_dot(pdg, options))?;
+    Ok(())
+}
+
+fn write_cluster(dot: &mut String, pdg: &ExportablePDG, options: &DotOptions, label: &str, cluster: &ModuleCluster, depth: usize, cluster_count: &mut usize) {
+    let indent = "  ".repeat(depth);
+    writeln!(dot, "{}subgraph cluster_{} {{", indent, cluster_count).unwrap();
+    writeln!(dot, "{}  label=\"{}\";", indent, escape(label)).unwrap();
+    *cluster_count += 1;
+
+    for idx in &cluster.nodes {
+        let node = &pdg.vertices[*idx];
+        let tooltip = if node.file.is_empty() { String::new() } else { format!("{}:{}", node.file, node.line) };
+        writeln!(dot, "{}  n{} [label=\"{}\", shape={}, fillcolor=\"{}\", tooltip=\"{}\"];", indent, idx, escape(&node_label(node, &options.formats)),
+            NodeShape::from(node.kind).name(), NodeColour::from(node.kind).to_hex(), escape(&tooltip)).unwrap();
+    }
+    for (instance, child) in &cluster.children {
+        write_cluster(dot, pdg, options, instance, child, depth + 1, cluster_count);
+    }
+    writeln!(dot, "{}}}", indent).unwrap();
+}
+
+/// Escapes a string for use in a quoted DOT attribute. Multi-line strings are left-aligned, to keep value trees readable.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\l"),
+            _ => escaped.push(c)
+        }
+    }
+    if s.contains('\n') {
+        escaped.push_str("\\l");
+    }
+    escaped
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AttributeType {
+    String,
+    Int,
+    Long,
+    Boolean
+}
+
+impl AttributeType {
+    fn graphml_name(self) -> &'static str {
+        match self {
+            AttributeType::String => "string",
+            AttributeType::Int => "int",
+            AttributeType::Long => "long",
+            AttributeType::Boolean => "boolean"
+        }
+    }
+
+    fn gexf_name(self) -> &'static str {
+        match self {
+            AttributeType::String => "string",
+            AttributeType::Int => "integer",
+            AttributeType::Long => "long",
+            AttributeType::Boolean => "boolean"
+        }
+    }
+}
+
+/// The node attributes of the GraphML and GEXF exports, one per field of the node.
+/// The simulation value is split into its type, width, raw bits and the value as shown in the viewer.
+const NODE_ATTRIBUTES: &[(&str, AttributeType)] = &[
+    ("name", AttributeType::String),
+    ("file", AttributeType::String),
+    ("line", AttributeType::Int),
+    ("char", AttributeType::Int),
+    ("end_char", AttributeType::Int),
+    ("kind", AttributeType::String),
+    ("clocked", AttributeType::Boolean),
+    ("module_path", AttributeType::String),
+    ("related_signal", AttributeType::String),
+    ("assigns_to", AttributeType::String),
+    ("predicate", AttributeType::String),
+    ("provenance", AttributeType::String),
+    ("sim_type", AttributeType::String),
+    ("sim_width", AttributeType::Int),
+    ("sim_bits", AttributeType::String),
+    ("sim_value", AttributeType::String),
+    ("golden_expected_bits", AttributeType::String),
+    ("earliest_golden_mismatch", AttributeType::Boolean),
+    ("timestamp", AttributeType::Long),
+    ("is_chisel_assignment", AttributeType::Boolean)
+];
+
+const EDGE_ATTRIBUTES: &[(&str, AttributeType)] = &[
+    ("kind", AttributeType::String),
+    ("clocked", AttributeType::Boolean),
+    ("value", AttributeType::String)
+];
+
+/// The values of `NODE_ATTRIBUTES` for a node. Absent optional fields have no value.
+fn node_attribute_values(node: &ExportablePDGNode, formats: &FormatOverrides) -> Vec<Option<String>> {
+    let sim_data = node.sim_data.as_ref();
+    vec![
+        Some(node.name.clone()),
+        Some(node.file.clone()),
+        Some(node.line.to_string()),
+        Some(node.char.to_string()),
+        node.end_char.map(|c| c.to_string()),
+        Some(format!("{:?}", node.kind)),
+        Some(node.clocked.to_string()),
+        Some(node.module_path.iter().filter(|p| !p.is_empty()).join(".")),
+        node.related_signal.as_ref().map(signal_key),
+        node.assigns_to.clone(),
+        node.predicate.clone(),
+        // Indices of the vertices in the FIRRTL representation
+        Some(node.provenance.iter().map(|p| p.index).join(",")),
+        sim_data.and_then(|v| v.type_name.clone()),
+        sim_data.map(|v| v.width.to_string()),
+        sim_data.map(|v| v.bits.clone()),
+        interpret_node_value(node, formats).map(|t| t.value),
+        node.golden_mismatch.as_ref().map(|m| m.expected.clone()),
+        node.golden_mismatch.as_ref().map(|m| m.earliest.to_string()),
+        Some(node.timestamp.to_string()),
+        Some(node.is_chisel_assignment.to_string())
+    ]
+}
+
+/// The values of `EDGE_ATTRIBUTES` for an edge. Like in the viewer, the value is the one of the node the edge points to.
+fn edge_attribute_values(pdg: &ExportablePDG, edge: &ExportablePDGEdge, formats: &FormatOverrides) -> Vec<Option<String>> {
+    vec![
+        Some(format!("{:?}", edge.kind)),
+        Some(edge.clocked.to_string()),
+        interpret_node_value(&pdg.vertices[edge.to as usize], formats).map(|t| t.value)
+    ]
+}
+
+/// Renders a (D)PDG as GraphML, with every node field as a typed attribute
+pub fn pdg_to_graphml(pdg: &ExportablePDG, formats: &FormatOverrides) -> String {
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(xml, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">"#).unwrap();
+    for (name, tpe) in NODE_ATTRIBUTES {
+        writeln!(xml, r#"  <key id="n_{0}" for="node" attr.name="{0}" attr.type="{1}"/>"#, name, tpe.graphml_name()).unwrap();
+    }
+    for (name, tpe) in EDGE_ATTRIBUTES {
+        writeln!(xml, r#"  <key id="e_{0}" for="edge" attr.name="{0}" attr.type="{1}"/>"#, name, tpe.graphml_name()).unwrap();
+    }
+    writeln!(xml, r#"  <graph id="pdg" edgedefault="directed">"#).unwrap();
+
+    for (idx, v) in pdg.vertices.iter().enumerate() {
+        writeln!(xml, r#"    <node id="n{}">"#, idx).unwrap();
+        for ((name, _), value) in NODE_ATTRIBUTES.iter().zip(node_attribute_values(v, formats)) {
+            if let Some(value) = value {
+                writeln!(xml, r#"      <data key="n_{}">{}</data>"#, name, escape_xml(&value)).unwrap();
+            }
+        }
+        writeln!(xml, "    </node>").unwrap();
+    }
+    for (idx, e) in pdg.edges.iter().enumerate() {
+        writeln!(xml, r#"    <edge id="e{}" source="n{}" target="n{}">"#, idx, e.from, e.to).unwrap();
+        for ((name, _), value) in EDGE_ATTRIBUTES.iter().zip(edge_attribute_values(pdg, e, formats)) {
+            if let Some(value) = value {
+                writeln!(xml, r#"      <data key="e_{}">{}</data>"#, name, escape_xml(&value)).unwrap();
+            }
+        }
+        writeln!(xml, "    </edge>").unwrap();
+    }
+
+    writeln!(xml, "  </graph>").unwrap();
+    writeln!(xml, "</graphml>").unwrap();
+    xml
+}
+
+pub fn write_graphml<P: AsRef<Path>>(pdg: &ExportablePDG, formats: &FormatOverrides, path: P) -> Result<()> {
+    fs::write(path, pdg_to_graphml(pdg, formats))?;
+    Ok(())
+}
+
+/// Renders a (D)PDG as GEXF, with every node field as a typed attribute. The nodes and edges are coloured
+/// and shaped like in the viewer.
+pub fn pdg_to_gexf(pdg: &ExportablePDG, formats: &FormatOverrides) -> String {
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(xml, r#"<gexf xmlns="http://gexf.net/1.3" xmlns:viz="http://gexf.net/1.3/viz" version="1.3">"#).unwrap();
+    writeln!(xml, r#"  <graph defaultedgetype="directed">"#).unwrap();
+    for (class, attributes) in [("node", NODE_ATTRIBUTES), ("edge", EDGE_ATTRIBUTES)] {
+        writeln!(xml, r#"    <attributes class="{}">"#, class).unwrap();
+        for (id, (name, tpe)) in attributes.iter().enumerate() {
+            writeln!(xml, r#"      <attribute id="{}" title="{}" type="{}"/>"#, id, name, tpe.gexf_name()).unwrap();
+        }
+        writeln!(xml, "    </attributes>").unwrap();
+    }
+
+    writeln!(xml, "    <nodes>").unwrap();
+    for (idx, v) in pdg.vertices.iter().enumerate() {
+        writeln!(xml, r#"      <node id="{}" label="{}">"#, idx, escape_xml(&node_label(v, formats))).unwrap();
+        write_gexf_attvalues(&mut xml, node_attribute_values(v, formats));
+        let (r, g, b) = hex_to_rgb(&NodeColour::from(v.kind).to_hex());
+        writeln!(xml, r#"        <viz:color r="{}" g="{}" b="{}"/>"#, r, g, b).unwrap();
+        writeln!(xml, r#"        <viz:shape value="{}"/>"#, NodeShape::from(v.kind).gexf_name()).unwrap();
+        writeln!(xml, "      </node>").unwrap();
+    }
+    writeln!(xml, "    </nodes>").unwrap();
+
+    writeln!(xml, "    <edges>").unwrap();
+    for (idx, e) in pdg.edges.iter().enumerate() {
+        writeln!(xml, r#"      <edge id="{}" source="{}" target="{}">"#, idx, e.from, e.to).unwrap();
+        write_gexf_attvalues(&mut xml, edge_attribute_values(pdg, e, formats));
+        let (r, g, b) = hex_to_rgb(&EdgeColour::from(e.kind).to_hex());
+        writeln!(xml, r#"        <viz:color r="{}" g="{}" b="{}"/>"#, r, g, b).unwrap();
+        writeln!(xml, r#"        <viz:shape value="{}"/>"#, if e.clocked { "dotted" } else { "solid" }).unwrap();
+        writeln!(xml, "      </edge>").unwrap();
+    }
+    writeln!(xml, "    </edges>").unwrap();
+
+    writeln!(xml, "  </graph>").unwrap();
+    writeln!(xml, "</gexf>").unwrap();
+    xml
+}
+
+pub fn write_gexf<P: AsRef<Path>>(pdg: &ExportablePDG, formats: &FormatOverrides, path: P) -> Result<()> {
+    fs::write(path, pdg_to_gexf(pdg, formats))?;
+    Ok(())
+}
+
+fn write_gexf_attvalues(xml: &mut String, values: Vec<Option<String>>) {
+    writeln!(xml, "        <attvalues>").unwrap();
+    for (id, value) in values.into_iter().enumerate() {
+        if let Some(value) = value {
+            writeln!(xml, r#"          <attvalue for="{}" value="{}"/>"#, id, escape_xml(&value)).unwrap();
+        }
+    }
+    writeln!(xml, "        </attvalues>").unwrap();
+}
+
+/// Converts a colour in the `#RRGGBB` format to its components
+fn hex_to_rgb(hex: &str) -> (u8, u8, u8) {
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or_default();
+    (component(1), component(3), component(5))
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
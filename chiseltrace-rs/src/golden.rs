@@ -0,0 +1,257 @@
+use std::{borrow::Cow, collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{errors::Error, pdg_spec::{ExportablePDG, ExportablePDGNode, GoldenMismatch}, query::{build_lookup_tables, reachable_from}, root_cause::find_criterion, value_format::signal_key};
+
+/// Expected values of signals per timestamp, such as the values of a passing run or the dump of a golden model
+#[derive(Debug, Clone, Default)]
+pub struct GoldenValues {
+    /// The expected values, keyed by the signal (as in the format overrides) and the timestamp
+    values: HashMap<(String, i64), GoldenValue>
+}
+
+/// An expected value. Negative numbers can only be converted to bits once the width of the signal is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GoldenValue {
+    /// The raw bits, most significant bit first
+    Bits(String),
+    Negative(i128)
+}
+
+/// An entry of a JSON golden value file
+#[derive(Deserialize)]
+struct GoldenEntry {
+    signal: String,
+    cycle: i64,
+    value: GoldenEntryValue
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GoldenEntryValue {
+    Unsigned(u64),
+    Signed(i64),
+    String(String)
+}
+
+impl GoldenValues {
+    /// Takes the values of all signals in a (D)PDG, including their value history if it was attached
+    pub fn from_pdg(pdg: &ExportablePDG) -> Self {
+        let mut values = HashMap::new();
+        for v in &pdg.vertices {
+            let Some(signal) = &v.related_signal else {
+                continue;
+            };
+            let key = signal_key(signal);
+            for (t, value) in v.sim_history.iter().flat_map(|h| h.window.iter()) {
+                values.insert((key.clone(), *t), GoldenValue::Bits(value.bits.clone()));
+            }
+            if let Some(value) = &v.sim_data {
+                values.insert((key, v.timestamp), GoldenValue::Bits(value.bits.clone()));
+            }
+        }
+        GoldenValues { values }
+    }
+
+    /// Reads the values dumped by a golden model, as a JSON array of `{"signal", "cycle", "value"}` objects if the
+    /// file has a `.json` extension, and otherwise as CSV with `signal,cycle,value` lines. Cycle `c` has the values after
+    /// `c` rising clock edges, so cycle 0 has the values from before the first edge. Values are decimal numbers, or
+    /// hexadecimal or binary with a `0x` or `0b` prefix. Negative numbers are compared in two's complement, at the width
+    /// of the signal.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let entries = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")) {
+            serde_json::from_str::<Vec<GoldenEntry>>(&contents)?.into_iter()
+                .map(|e| {
+                    let value = match e.value {
+                        GoldenEntryValue::Unsigned(n) => n.to_string(),
+                        GoldenEntryValue::Signed(n) => n.to_string(),
+                        GoldenEntryValue::String(s) => s
+                    };
+                    (e.signal, e.cycle, value)
+                })
+                .collect()
+        } else {
+            parse_csv(&contents)?
+        };
+
+        let mut values = HashMap::new();
+        for (signal, cycle, value) in entries {
+            let parsed = parse_value(&value)
+                .ok_or_else(|| Error::GoldenValueParseError(format!("\"{}\" of {} at cycle {} is not a number", value, signal, cycle)))?;
+            values.insert((signal, cycle), parsed);
+        }
+        Ok(GoldenValues { values })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The expected bits of the signal of a node at the timestamp of the node. Negative values are only known for nodes
+    /// with a simulation value, which gives the width of the signal.
+    pub fn expected(&self, node: &ExportablePDGNode) -> Option<Cow<'_, str>> {
+        let signal = signal_key(node.related_signal.as_ref()?);
+        match self.values.get(&(signal, node.timestamp))? {
+            GoldenValue::Bits(bits) => Some(Cow::Borrowed(bits)),
+            GoldenValue::Negative(value) => Some(Cow::Owned(twos_complement(*value, node.sim_data.as_ref()?.width)))
+        }
+    }
+}
+
+/// Parses `signal,cycle,value` lines. Empty lines, lines starting with `#` and a header line are skipped.
+fn parse_csv(contents: &str) -> Result<Vec<(String, i64, String)>> {
+    let mut entries = vec![];
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let columns = line.split(',').map(|c| c.trim()).collect::<Vec<_>>();
+        let [signal, cycle, value] = columns.as_slice() else {
+            anyhow::bail!(Error::GoldenValueParseError(format!("line {} does not have three columns", line_idx + 1)));
+        };
+        match cycle.parse() {
+            Ok(cycle) => entries.push((signal.to_string(), cycle, value.to_string())),
+            Err(_) if entries.is_empty() => (), // Header
+            Err(_) => anyhow::bail!(Error::GoldenValueParseError(format!("line {} has an invalid cycle \"{}\"", line_idx + 1, cycle)))
+        }
+    }
+    Ok(entries)
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary number
+fn parse_value(value: &str) -> Option<GoldenValue> {
+    let value = value.trim().replace('_', "");
+    let bits = if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        hex.chars().map(|c| c.to_digit(16).map(|d| format!("{:04b}", d))).collect::<Option<String>>()?
+    } else if let Some(bin) = value.strip_prefix("0b").or_else(|| value.strip_prefix("0B")) {
+        bin.chars().all(|c| c == '0' || c == '1').then(|| bin.to_string())?
+    } else if value.starts_with('-') {
+        return value.parse::<i128>().ok().map(|v| if v < 0 { GoldenValue::Negative(v) } else { GoldenValue::Bits(format!("{:b}", v)) });
+    } else {
+        format!("{:b}", value.parse::<u128>().ok()?)
+    };
+    Some(GoldenValue::Bits(bits))
+}
+
+/// The two's complement bits of a number at the given width, most significant bit first
+fn twos_complement(value: i128, width: u32) -> String {
+    let mask = if width >= 128 { u128::MAX } else { (1u128 << width) - 1 };
+    format!("{:0width$b}", value as u128 & mask, width = width as usize)
+}
+
+/// Whether two bit strings hold the same value, regardless of their width
+pub fn same_bits(a: &str, b: &str) -> bool {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.eq_ignore_ascii_case(b)
+}
+
+/// Marks the nodes whose simulation value differs from the expected value. Returns the number of mismatches.
+pub fn annotate_mismatches(pdg: &mut ExportablePDG, golden: &GoldenValues) -> usize {
+    let mut mismatches = 0;
+    for node in &mut pdg.vertices {
+        let expected = match (golden.expected(node), &node.sim_data) {
+            (Some(expected), Some(value)) if !same_bits(&expected, &value.bits) => expected.into_owned(),
+            _ => continue
+        };
+        node.golden_mismatch = Some(GoldenMismatch { expected, earliest: false });
+        mismatches += 1;
+    }
+    mismatches
+}
+
+/// Marks the earliest mismatch with the golden reference that the criterion (transitively) depends on, and returns it
+pub fn mark_earliest_mismatch(pdg: &mut ExportablePDG) -> Option<usize> {
+    for m in pdg.vertices.iter_mut().filter_map(|v| v.golden_mismatch.as_mut()) {
+        m.earliest = false;
+    }
+    let criterion = find_criterion(pdg)?;
+    let (_, dep_to_edges, _) = build_lookup_tables(pdg);
+    let earliest = reachable_from(pdg, &dep_to_edges, criterion).into_iter()
+        .filter(|idx| pdg.vertices[*idx].golden_mismatch.is_some())
+        .min_by_key(|idx| (pdg.vertices[*idx].timestamp, *idx))?;
+    if let Some(m) = &mut pdg.vertices[earliest].golden_mismatch {
+        m.earliest = true;
+    }
+    Some(earliest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pdg_spec::{PDGSpecNodeKind, PDGSpecRelatedSignal, SimValue, SimValueKind};
+
+    use super::*;
+
+    fn node(signal_path: &str, field_path: &str, timestamp: i64, bits: &str) -> ExportablePDGNode {
+        let sim_data = SimValue { type_name: None, width: bits.len() as u32, bits: bits.into(), signed: false, kind: SimValueKind::Ground,
+            enum_variant: None, binary_point: None, fields: vec![], display: None };
+        ExportablePDGNode { file: "Top.scala".into(), line: 0, char: 0, end_char: None, name: signal_path.into(), kind: PDGSpecNodeKind::Connection,
+            clocked: false, module_path: vec![], related_signal: Some(PDGSpecRelatedSignal { signal_path: signal_path.into(), field_path: field_path.into() }),
+            assigns_to: None, predicate: None, provenance: vec![], sim_data: Some(sim_data), sim_history: None, golden_mismatch: None, timestamp,
+            is_chisel_assignment: true }
+    }
+
+    fn bits(value: &str) -> Option<GoldenValue> {
+        Some(GoldenValue::Bits(value.into()))
+    }
+
+    #[test]
+    fn values_are_decimal_hexadecimal_or_binary() {
+        assert_eq!(parse_value("42"), bits("101010"));
+        assert_eq!(parse_value(" 1_000 "), bits("1111101000"));
+        assert_eq!(parse_value("0x2A"), bits("00101010"));
+        assert_eq!(parse_value("0b101"), bits("101"));
+        assert_eq!(parse_value("-0"), bits("0"));
+        assert_eq!(parse_value("-3"), Some(GoldenValue::Negative(-3)));
+        assert_eq!(parse_value("0x2g"), None);
+        assert_eq!(parse_value("0b102"), None);
+        assert_eq!(parse_value("ten"), None);
+    }
+
+    #[test]
+    fn negative_values_are_twos_complement_at_the_signal_width() {
+        assert_eq!(twos_complement(-1, 4), "1111");
+        assert_eq!(twos_complement(-3, 8), "11111101");
+        assert_eq!(twos_complement(-1, 128), "1".repeat(128));
+
+        let golden = GoldenValues { values: HashMap::from([(("io.out".to_string(), 1), GoldenValue::Negative(-2))]) };
+        assert_eq!(golden.expected(&node("io.out", "", 1, "0000")).as_deref(), Some("1110"));
+        assert_eq!(golden.expected(&node("io.out", "", 2, "0000")), None);
+    }
+
+    #[test]
+    fn csv_headers_and_comments_are_skipped() {
+        let csv = "signal,cycle,value\n# reset\nio.out.bits, 0, 0x1\n\nio.valid,1,1\n";
+        let entries = parse_csv(csv).unwrap();
+        assert_eq!(entries, vec![
+            ("io.out.bits".to_string(), 0, "0x1".to_string()),
+            ("io.valid".to_string(), 1, "1".to_string()),
+        ]);
+
+        assert!(parse_csv("io.valid,1,1\nio.valid,two,0").is_err());
+        assert!(parse_csv("io.valid,1").is_err());
+    }
+
+    #[test]
+    fn mismatches_are_annotated_with_the_expected_value() {
+        let mut pdg = ExportablePDG {
+            vertices: vec![node("io.out", "bits", 0, "0101"), node("io.out", "bits", 1, "0110"), node("io.valid", "", 1, "1")],
+            edges: vec![]
+        };
+        let golden = GoldenValues { values: HashMap::from([
+            (("io.out.bits".to_string(), 0), GoldenValue::Bits("101".into())),
+            (("io.out.bits".to_string(), 1), GoldenValue::Bits("111".into())),
+            (("io.valid".to_string(), 1), GoldenValue::Negative(-1)),
+        ]) };
+        assert_eq!(annotate_mismatches(&mut pdg, &golden), 1);
+        assert_eq!(pdg.vertices[0].golden_mismatch, None);
+        assert_eq!(pdg.vertices[1].golden_mismatch, Some(GoldenMismatch { expected: "111".into(), earliest: false }));
+        // -1 is all ones at the width of the signal
+        assert_eq!(pdg.vertices[2].golden_mismatch, None);
+    }
+}
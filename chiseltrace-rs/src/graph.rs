@@ -0,0 +1,191 @@
+use crate::pdg_spec::{ExportablePDG, ExportablePDGEdge, ExportablePDGNode};
+
+/// An adjacency list representation of a (D)PDG. The vertices and edges are stored in the same way as in the
+/// [`ExportablePDG`], but every vertex additionally knows its incoming and outgoing edges. This makes neighbourhood
+/// lookups O(1) instead of requiring a scan over the entire edge list.
+#[derive(Debug, Clone)]
+pub struct AdjacencyPDG {
+    pub vertices: Vec<ExportablePDGNode>,
+    pub edges: Vec<ExportablePDGEdge>,
+    outgoing: Vec<Vec<u32>>,
+    incoming: Vec<Vec<u32>>
+}
+
+impl AdjacencyPDG {
+    pub fn new(vertices: Vec<ExportablePDGNode>, edges: Vec<ExportablePDGEdge>) -> Self {
+        let mut outgoing = vec![vec![]; vertices.len()];
+        let mut incoming = vec![vec![]; vertices.len()];
+        for (idx, edge) in edges.iter().enumerate() {
+            outgoing[edge.from as usize].push(idx as u32);
+            incoming[edge.to as usize].push(idx as u32);
+        }
+
+        AdjacencyPDG { vertices, edges, outgoing, incoming }
+    }
+
+    /// The edges that point from the given vertex to its dependencies.
+    pub fn outgoing(&self, vertex: u32) -> impl Iterator<Item = &ExportablePDGEdge> {
+        self.outgoing[vertex as usize].iter().map(|e| &self.edges[*e as usize])
+    }
+
+    /// The edges that point from the vertices that depend on the given vertex to the vertex.
+    pub fn incoming(&self, vertex: u32) -> impl Iterator<Item = &ExportablePDGEdge> {
+        self.incoming[vertex as usize].iter().map(|e| &self.edges[*e as usize])
+    }
+
+    /// Removes all vertices that are marked in `removed`, together with all edges that are connected to them.
+    /// The remaining vertices keep their relative order.
+    pub fn remove_vertices(self, removed: &[bool]) -> Self {
+        let mut remap = Vec::with_capacity(self.vertices.len());
+        let mut new_idx = 0;
+        for is_removed in removed {
+            if *is_removed {
+                remap.push(None);
+            } else {
+                remap.push(Some(new_idx));
+                new_idx += 1;
+            }
+        }
+
+        let vertices = self.vertices.into_iter()
+            .zip(removed)
+            .filter(|(_, is_removed)| !**is_removed)
+            .map(|(v, _)| v)
+            .collect::<Vec<_>>();
+
+        let edges = self.edges.into_iter().filter_map(|e| {
+            Some(ExportablePDGEdge { from: remap[e.from as usize]?, to: remap[e.to as usize]?, ..e })
+        }).collect::<Vec<_>>();
+
+        AdjacencyPDG::new(vertices, edges)
+    }
+
+    /// Computes the strongly connected components of the subgraph formed by the edges for which `include` returns true.
+    /// Returns the component index of every vertex.
+    pub fn strongly_connected_components(&self, include: impl Fn(&ExportablePDGEdge) -> bool) -> Vec<u32> {
+        // Iterative version of Tarjan's algorithm. A recursive implementation overflows the stack on large graphs.
+        const UNVISITED: u32 = u32::MAX;
+        let n = self.vertices.len();
+        let mut index = vec![UNVISITED; n];
+        let mut lowlink = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut component = vec![UNVISITED; n];
+        let mut scc_stack = vec![];
+        let mut next_index = 0;
+        let mut next_component = 0;
+
+        for start in 0..n as u32 {
+            if index[start as usize] != UNVISITED {
+                continue;
+            }
+
+            // (vertex, position in the outgoing edge list)
+            let mut call_stack = vec![(start, 0usize)];
+            while let Some((v, edge_pos)) = call_stack.pop() {
+                let vi = v as usize;
+                if edge_pos == 0 {
+                    index[vi] = next_index;
+                    lowlink[vi] = next_index;
+                    next_index += 1;
+                    scc_stack.push(v);
+                    on_stack[vi] = true;
+                }
+
+                let mut descended = false;
+                let out_edges = &self.outgoing[vi];
+                let mut pos = edge_pos;
+                while pos < out_edges.len() {
+                    let edge = &self.edges[out_edges[pos] as usize];
+                    pos += 1;
+                    if !include(edge) {
+                        continue;
+                    }
+                    let w = edge.to as usize;
+                    if index[w] == UNVISITED {
+                        call_stack.push((v, pos));
+                        call_stack.push((edge.to, 0));
+                        descended = true;
+                        break;
+                    } else if on_stack[w] {
+                        lowlink[vi] = lowlink[vi].min(index[w]);
+                    }
+                }
+                if descended {
+                    continue;
+                }
+
+                if lowlink[vi] == index[vi] {
+                    while let Some(w) = scc_stack.pop() {
+                        on_stack[w as usize] = false;
+                        component[w as usize] = next_component;
+                        if w == v {
+                            break;
+                        }
+                    }
+                    next_component += 1;
+                }
+
+                // Propagate the lowlink to the caller
+                if let Some((parent, _)) = call_stack.last() {
+                    let parent = *parent as usize;
+                    lowlink[parent] = lowlink[parent].min(lowlink[vi]);
+                }
+            }
+        }
+
+        component
+    }
+}
+
+impl From<ExportablePDG> for AdjacencyPDG {
+    fn from(value: ExportablePDG) -> Self {
+        AdjacencyPDG::new(value.vertices, value.edges)
+    }
+}
+
+impl From<AdjacencyPDG> for ExportablePDG {
+    fn from(value: AdjacencyPDG) -> Self {
+        ExportablePDG { vertices: value.vertices, edges: value.edges }
+    }
+}
+
+/// A union-find structure with path compression and union by size.
+pub struct DisjointSet {
+    parent: Vec<u32>,
+    size: Vec<u32>
+}
+
+impl DisjointSet {
+    pub fn new(n: usize) -> Self {
+        DisjointSet { parent: (0..n as u32).collect(), size: vec![1; n] }
+    }
+
+    pub fn find(&mut self, x: u32) -> u32 {
+        let mut root = x;
+        while self.parent[root as usize] != root {
+            root = self.parent[root as usize];
+        }
+        // Compress the path
+        let mut current = x;
+        while self.parent[current as usize] != root {
+            let next = self.parent[current as usize];
+            self.parent[current as usize] = root;
+            current = next;
+        }
+        root
+    }
+
+    /// Merges the sets of `a` and `b`. Returns the representative of the merged set.
+    pub fn union(&mut self, a: u32, b: u32) -> u32 {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return a;
+        }
+        if self.size[a as usize] < self.size[b as usize] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b as usize] = a;
+        self.size[a as usize] += self.size[b as usize];
+        a
+    }
+}
@@ -6,6 +6,11 @@ use anyhow::Result;
 
 use crate::{pdg_spec::{PDGSpec, PDGSpecEdge, PDGSpecEdgeKind, PDGSpecNode, PDGSpecNodeKind}, errors::Error};
 
+/// The time slot of the initial state of the circuit: the initial (reset) values of the registers, and the wires that these
+/// are computed from. Timestamp `t >= 0` holds the wires that are evaluated after `t` rising clock edges, and the register
+/// updates of the `t`-th edge. Every DPDG node has a timestamp of at least this value.
+pub const INITIAL_STATE_TIMESTAMP: i64 = -1;
+
 pub struct GraphBuilder {
     reader: VcdReader,
     pdg: PDGSpec,
@@ -67,7 +72,9 @@ pub enum GraphProcessingType {
 }
 
 impl GraphBuilder {
-    pub fn new(vcd_path: impl AsRef<Path>, extra_scopes: Vec<String>, pdg: PDGSpec) -> Result<GraphBuilder> {
+    pub fn new(vcd_path: impl AsRef<Path>, extra_scopes: Vec<String>, mut pdg: PDGSpec) -> Result<GraphBuilder> {
+        // Probe nodes are recognised by their kind, also when the PDG was not read with PDGSpec::from_file
+        pdg.migrate();
         let vcd_reader = VcdReader::new(vcd_path, extra_scopes)?;
 
         // Link up the nodes for easier processing
@@ -124,6 +131,7 @@ impl GraphBuilder {
             let mut new_reg_providers: HashMap<String, Rc<RefCell<DynPDGNode>>> = HashMap::new();
             let mut controlflow_providers: HashMap<Rc<PDGSpecNode>, Rc<RefCell<DynPDGNode>>> = HashMap::new();
             let mut new_nodes = vec![];
+            let mut initial_registers = vec![];
 
             // Get the ready delayed statements
             let mut ready_statements = vec![];
@@ -153,8 +161,8 @@ impl GraphBuilder {
                 let node = self.linked_nodes[*stmt as usize].borrow();
                 // Without this fix, we get a situation where registers of timestamp x can depend on wires from timestamp x, which is clearly
                 // incorrect if you operate under the assumption that on each rising edge, the registers update, THEN the wires that depend on those
-                // update
-                let node_timestamp = if node.inner.clocked { corrected_timestamp } else { corrected_timestamp.saturating_sub(1) };
+                // update. The registers of this cycle update on the edge that ends it, so they belong to the next time slot.
+                let node_timestamp = if node.inner.clocked { corrected_timestamp + 1 } else { corrected_timestamp };
                 let dpdg_node = Rc::new(RefCell::new(DynPDGNode {inner: node.inner.clone(), timestamp: node_timestamp, dependencies: vec![]}));
                 new_nodes.push((self.linked_nodes[*stmt as usize].clone(), dpdg_node.clone()));
 
@@ -180,7 +188,13 @@ impl GraphBuilder {
                                 // Handle register resets.
                                 if corrected_timestamp == 0 || self.reader.reset_val == vcd::Value::V1 {
                                     // println!("Register with reset: {:?}", node.inner.name);
-                                    dpdg_node.borrow_mut().timestamp -= 1;
+                                    // The reset value is the state during this cycle. For the first cycle, that is the initial state.
+                                    if corrected_timestamp == 0 {
+                                        dpdg_node.borrow_mut().timestamp = INITIAL_STATE_TIMESTAMP;
+                                        initial_registers.push(dpdg_node.clone());
+                                    } else {
+                                        dpdg_node.borrow_mut().timestamp = corrected_timestamp;
+                                    }
                                     self.dependency_state.insert(symb.clone(), dpdg_node.clone());
                                 }
                             } else {
@@ -246,7 +260,7 @@ impl GraphBuilder {
                                     // Just create a new one. I know this is a bit of an afterthought, but this is a simple way to make
                                     // the dynamic slicing work. It doesn't need further processing anyway, so we can create as many nodes
                                     // as we want.
-                                    let dep = Rc::new(RefCell::new(DynPDGNode {inner: dep_node.borrow().inner.clone(), timestamp: corrected_timestamp - 1, dependencies: vec![]}));
+                                    let dep = Rc::new(RefCell::new(DynPDGNode {inner: dep_node.borrow().inner.clone(), timestamp: corrected_timestamp, dependencies: vec![]}));
                                     dpdg_node.borrow_mut().dependencies.push((dep.clone(), dep_edge.kind));
                                 }
                             }
@@ -275,6 +289,8 @@ impl GraphBuilder {
                 }
             }
 
+            move_to_initial_state(&initial_registers);
+
             // If there are delayed statements, we need to save a snapshot of the dependencies, because
             // control flow and index flow need to be of the current timestamp, while the data flow is actually not (for SRAM at least).
             if delayed_statements_present {
@@ -361,6 +377,23 @@ impl GraphBuilder {
     }
 }
 
+/// Moves the wires that the initial values of registers are computed from to the initial state time slot. These are evaluated
+/// before the first clock edge, like the other wires of the first cycle, but only the initial state depends on them.
+fn move_to_initial_state(initial_registers: &[Rc<RefCell<DynPDGNode>>]) {
+    let mut stack = initial_registers.iter()
+        .flat_map(|r| r.borrow().dependencies.iter().map(|(dep, _)| dep.clone()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    while let Some(node) = stack.pop() {
+        let mut node = node.borrow_mut();
+        // Registers and nodes that were already moved are skipped
+        if node.inner.clocked || node.timestamp != 0 {
+            continue;
+        }
+        node.timestamp = INITIAL_STATE_TIMESTAMP;
+        stack.extend(node.dependencies.iter().map(|(dep, _)| dep.clone()));
+    }
+}
+
 impl VcdReader {
     fn new(vcd_path: impl AsRef<Path>, extra_scopes: Vec<String>) -> Result<Self> {
         let file = File::open(vcd_path)?;
@@ -1,7 +1,19 @@
 pub mod pdg_spec;
 pub mod conversion;
+pub mod graph;
 pub mod slicing;
 pub mod errors;
 pub mod graphbuilder;
 pub mod sim_data_injection;
+pub mod sarif;
+pub mod predicates;
+pub mod value_format;
+pub mod translation;
+pub mod export;
+pub mod pipeline;
+pub mod query;
+pub mod golden;
+pub mod root_cause;
+pub mod diff;
+pub mod x_propagation;
 pub mod util;
\ No newline at end of file
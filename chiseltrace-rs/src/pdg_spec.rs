@@ -1,6 +1,11 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, fs::File, io::BufReader, path::Path, rc::Rc};
 
-use serde::{Serialize, Deserialize};
+use anyhow::Result;
+use serde::{Serialize, Deserialize, Deserializer};
+
+/// Older versions of the Chisel extension did not have a separate node kind for probes. Instead, probe nodes
+/// were recognised by this name prefix.
+const LEGACY_PROBE_PREFIX: &str = "defnode_probe";
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PDGSpec {
@@ -14,6 +19,25 @@ impl PDGSpec {
     pub fn _empty() -> Self {
         PDGSpec { vertices: vec![], edges: vec![], predicates: vec![], cfg: vec![] }
     }
+
+    /// Reads a PDG as exported by the Chisel extension. PDGs from older versions are migrated to the current format.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut deser = serde_json::Deserializer::from_reader(reader);
+        deser.disable_recursion_limit();
+        let mut pdg = PDGSpec::deserialize(&mut deser)?;
+        pdg.migrate();
+        Ok(pdg)
+    }
+
+    /// Brings a PDG from an older version of the Chisel extension up to date. Running this on an up-to-date PDG has no effect.
+    pub fn migrate(&mut self) {
+        for v in self.vertices.iter_mut().chain(self.predicates.iter_mut()) {
+            if v.kind != PDGSpecNodeKind::Probe && v.name.starts_with(LEGACY_PROBE_PREFIX) {
+                v.kind = PDGSpecNodeKind::Probe;
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
@@ -22,6 +46,9 @@ pub struct PDGSpecNode {
     pub file: String,
     pub line: u32,
     pub char: u32,
+    /// The column where the source statement ends. Only present if the source locator contains a column range.
+    #[serde(default)]
+    pub end_char: Option<u32>,
     pub name: String,
     pub kind: PDGSpecNodeKind,
     pub clocked: bool,
@@ -42,6 +69,8 @@ pub enum PDGSpecNodeKind {
     IO,
     Connection,
     ControlFlow,
+    /// Instrumentation inserted by the Chisel extension to resolve dynamic dependencies. Not part of the source.
+    Probe
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
@@ -132,24 +161,26 @@ pub struct ExportableSlice {
 pub struct ExportableSliceStatement {
     pub file: String,
     pub line: u32,
-    pub char: u32
+    pub char: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_char: Option<u32>
 }
 
 impl From<ExportablePDGNode> for ExportableSliceStatement {
     fn from(value: ExportablePDGNode) -> Self {
-        ExportableSliceStatement { file: value.file, line: value.line, char: value.char }
+        ExportableSliceStatement { file: value.file, line: value.line, char: value.char, end_char: value.end_char }
     }
 }
 
 impl From<PDGSpecNode> for ExportableSliceStatement {
     fn from(value: PDGSpecNode) -> Self {
-        ExportableSliceStatement { file: value.file, line: value.line, char: value.char }
+        ExportableSliceStatement { file: value.file, line: value.line, char: value.char, end_char: value.end_char }
     }
 }
 
 impl From<Rc<PDGSpecNode>> for ExportableSliceStatement {
     fn from(value: Rc<PDGSpecNode>) -> Self {
-        ExportableSliceStatement { file: value.file.clone(), line: value.line, char: value.char }
+        ExportableSliceStatement { file: value.file.clone(), line: value.line, char: value.char, end_char: value.end_char }
     }
 }
 
@@ -158,25 +189,138 @@ pub struct ExportablePDGNode {
     pub file: String,
     pub line: u32,
     pub char: u32,
+    #[serde(default)]
+    pub end_char: Option<u32>,
     pub name: String,
     pub kind: PDGSpecNodeKind,
     pub clocked: bool,
     pub module_path: Vec<String>,
     pub related_signal: Option<PDGSpecRelatedSignal>,
-    pub sim_data: Option<String>,
+    #[serde(default)]
+    pub assigns_to: Option<String>,
+    /// The reconstructed predicate expression of a control flow statement
+    #[serde(default)]
+    pub predicate: Option<String>,
+    /// The FIRRTL statements that were merged into this node during the conversion to Chisel
+    #[serde(default)]
+    pub provenance: Vec<FirrtlProvenance>,
+    /// The value of the related signal at the timestamp of this node
+    #[serde(default, deserialize_with = "deserialize_sim_data")]
+    pub sim_data: Option<SimValue>,
+    /// The values of the related signal around the timestamp of this node, if requested during injection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sim_history: Option<SimHistory>,
+    /// Set if the value differs from the value expected by a golden reference, if one was given during injection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub golden_mismatch: Option<GoldenMismatch>,
     pub timestamp: i64,
     pub is_chisel_assignment: bool
 }
 
 impl From<PDGSpecNode> for ExportablePDGNode {
     fn from(value: PDGSpecNode) -> Self {
-        ExportablePDGNode { file: value.file, line: value.line, char: value.char, name: value.name, kind: value.kind,
-            clocked: value.clocked, module_path: value.module_path, related_signal: value.related_signal, sim_data: None,
-            is_chisel_assignment: value.is_chisel_statement, timestamp: 0
+        ExportablePDGNode { file: value.file, line: value.line, char: value.char, end_char: value.end_char, name: value.name, kind: value.kind,
+            clocked: value.clocked, module_path: value.module_path, related_signal: value.related_signal, assigns_to: value.assigns_to, predicate: None, provenance: vec![], sim_data: None, sim_history: None, golden_mismatch: None,
+            is_chisel_assignment: value.is_chisel_statement && value.kind != PDGSpecNodeKind::Probe, timestamp: 0
         }
     }
 }
 
+/// Refers to a vertex of the FIRRTL-level (D)PDG that a converted graph was created from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct FirrtlProvenance {
+    pub index: u32,
+    pub name: String
+}
+
+/// A typed simulation value, translated using the Tywaves type information.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct SimValue {
+    /// The source language type, such as `UInt<8>`
+    pub type_name: Option<String>,
+    pub width: u32,
+    /// The raw bits, most significant bit first. Undefined bits are kept as `x` or `z`.
+    pub bits: String,
+    pub signed: bool,
+    #[serde(default)]
+    pub kind: SimValueKind,
+    /// The name of the variant of a ChiselEnum value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enum_variant: Option<String>,
+    /// The number of fractional bits of a fixed-point or interval value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_point: Option<u32>,
+    /// The fields of a bundle or the elements of a vec, in declaration order. Empty for ground types.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<(String, SimValue)>,
+    /// The value in a display format chosen by the user, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>
+}
+
+impl SimValue {
+    /// Converts a value in the string form of older versions: the raw bits, optionally preceded by the Chisel type
+    /// (e.g. `UInt<8> 00010010`). Strings that do not end in raw bits are only kept for display.
+    fn from_legacy(value: &str) -> Self {
+        let (type_name, bits) = match value.rsplit_once(' ') {
+            Some((type_name, bits)) => (Some(type_name.to_string()), bits),
+            None => (None, value)
+        };
+        let legacy = SimValue { type_name: None, width: 0, bits: String::new(), signed: false, kind: SimValueKind::Ground,
+            enum_variant: None, binary_point: None, fields: vec![], display: None };
+        if bits.is_empty() || !bits.chars().all(|c| matches!(c, '0' | '1' | 'x' | 'X' | 'z' | 'Z')) {
+            return SimValue { display: Some(value.to_string()), ..legacy };
+        }
+        let signed = type_name.as_deref().is_some_and(|t| t.starts_with("SInt"));
+        SimValue { type_name, width: bits.len() as u32, bits: bits.to_lowercase(), signed, ..legacy }
+    }
+}
+
+/// Reads `sim_data` as a [`SimValue`], or in the string form of DPDGs that were written by older versions.
+fn deserialize_sim_data<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<SimValue>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum SimData {
+        Value(SimValue),
+        Legacy(String)
+    }
+
+    Ok(Option::<SimData>::deserialize(deserializer)?.map(|data| match data {
+        SimData::Value(value) => value,
+        SimData::Legacy(value) => SimValue::from_legacy(&value)
+    }))
+}
+
+/// The values of a signal in the cycles around a DPDG node
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct SimHistory {
+    /// The value in the cycle before the node
+    pub previous: Option<SimValue>,
+    /// Whether the value of the node differs from the previous value
+    pub changed: bool,
+    /// The values per timestamp, including the timestamp of the node itself
+    pub window: Vec<(i64, SimValue)>
+}
+
+/// A simulation value that differs from the golden reference
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct GoldenMismatch {
+    /// The raw bits of the expected value, most significant bit first
+    pub expected: String,
+    /// Whether this is the earliest mismatch that the criterion depends on
+    #[serde(default)]
+    pub earliest: bool
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SimValueKind {
+    #[default]
+    Ground,
+    Bundle,
+    Vec
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct ExportablePDGEdge {
     pub from: u32,
@@ -189,4 +333,40 @@ impl From<PDGSpecEdge> for ExportablePDGEdge {
     fn from(value: PDGSpecEdge) -> Self {
         ExportablePDGEdge { from: value.from, to: value.to, kind: value.kind, clocked: value.clocked }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_with_sim_data(sim_data: &str) -> ExportablePDGNode {
+        let json = format!(r#"{{"file": "Top.scala", "line": 1, "char": 0, "name": "out", "kind": "Connection", "clocked": false,
+            "module_path": [], "related_signal": null, "sim_data": {}, "timestamp": 0, "is_chisel_assignment": true}}"#, sim_data);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn sim_data_of_older_versions_is_read() {
+        let value = node_with_sim_data(r#""UInt<8> 0001001x""#).sim_data.unwrap();
+        assert_eq!(value.type_name.as_deref(), Some("UInt<8>"));
+        assert_eq!((value.bits.as_str(), value.width, value.signed), ("0001001x", 8, false));
+
+        let value = node_with_sim_data(r#""1110""#).sim_data.unwrap();
+        assert_eq!((value.type_name, value.bits.as_str(), value.width), (None, "1110", 4));
+
+        let value = node_with_sim_data(r#""SInt<4> 1110""#).sim_data.unwrap();
+        assert!(value.signed);
+
+        let value = node_with_sim_data(r#""State IDLE""#).sim_data.unwrap();
+        assert_eq!((value.bits.as_str(), value.display.as_deref()), ("", Some("State IDLE")));
+
+        assert!(node_with_sim_data("null").sim_data.is_none());
+    }
+
+    #[test]
+    fn sim_data_round_trips() {
+        let value = SimValue { type_name: Some("UInt<4>".into()), width: 4, bits: "0101".into(), signed: false, kind: SimValueKind::Ground,
+            enum_variant: None, binary_point: None, fields: vec![], display: None };
+        let node = node_with_sim_data(&serde_json::to_string(&value).unwrap());
+        assert_eq!(node.sim_data, Some(value));
+    }
+}
@@ -0,0 +1,127 @@
+use std::{collections::{HashMap, HashSet}, path::PathBuf, time::{Duration, Instant}};
+
+use anyhow::Result;
+
+use crate::{conversion::{dpdg_make_exportable, ConversionOptions, PassManager}, golden::{mark_earliest_mismatch, GoldenValues}, graphbuilder::{CriterionType, GraphBuilder, GraphProcessingType, INITIAL_STATE_TIMESTAMP}, pdg_spec::{ExportablePDG, ExportablePDGNode, PDGSpec, PDGSpecNodeKind}, sim_data_injection::SimDataInjector, util::read_source_files};
+
+/// Everything that is needed to trace a criterion through a simulation. Shared by the viewer and the headless CLI.
+#[derive(Debug, Clone)]
+pub struct TraceConfig {
+    pub criterion: CriterionType,
+    pub pdg_path: PathBuf,
+    pub vcd_path: PathBuf,
+    /// Without HGLDD files, the values are injected without their Chisel types
+    pub hgldd_path: Option<PathBuf>,
+    pub top_module: String,
+    pub extra_scopes: Vec<String>,
+    pub max_timesteps: Option<u64>,
+    pub data_only: bool,
+    pub conversion_passes: Vec<String>,
+    /// The number of cycles before and after each node for which the value of its signal is attached
+    pub value_history: Option<u32>,
+    /// A CSV or JSON file with the values that a golden model expects, which the simulation values are checked against
+    pub golden_values: Option<PathBuf>
+}
+
+/// A traced DPDG, in both the FIRRTL and the Chisel representation
+pub struct Trace {
+    pub firrtl: ExportablePDG,
+    pub chisel: ExportablePDG,
+    /// The lines of the source files that the DPDG refers to
+    pub source_files: HashMap<String, Vec<String>>,
+    /// How long each stage of the pipeline took, in order
+    pub timings: Vec<(&'static str, Duration)>
+}
+
+/// Builds the DPDG of a criterion, adds the simulation data and converts it to the Chisel representation.
+/// Timestamps start at zero, which holds the initial state.
+pub fn build_trace(config: &TraceConfig) -> Result<Trace> {
+    let mut timings = vec![];
+    let mut now = Instant::now();
+    let mut lap = |stage: &'static str| {
+        timings.push((stage, now.elapsed()));
+        now = Instant::now();
+    };
+    let pdg_raw = PDGSpec::from_file(&config.pdg_path)?;
+    lap("PDG read");
+
+    // Build the DPDG
+    let mut builder = GraphBuilder::new(&config.vcd_path, config.extra_scopes.clone(), pdg_raw)?;
+    let processing_type = if config.data_only { GraphProcessingType::DataOnly } else { GraphProcessingType::Normal };
+    let dpdg = builder.process(&config.criterion, config.max_timesteps.map(|t| t as i64), processing_type)?;
+    lap("DPDG build");
+
+    let mut dpdg = dpdg_make_exportable(dpdg);
+    lap("Exportable");
+
+    // Add simulation data. This is done before the conversion, so both the FIRRTL and the Chisel representation have it.
+    // The conversion keeps the simulation data of the statement that a Chisel node is named after.
+    let mut injector = SimDataInjector::new(config.hgldd_path.as_deref(), config.extra_scopes.clone(), &config.top_module);
+    if let Some(window) = config.value_history {
+        injector = injector.with_value_history(window);
+    }
+    if let Some(path) = &config.golden_values {
+        injector = injector.with_golden_values(GoldenValues::from_file(path)?);
+    }
+    injector.inject_sim_data(&mut dpdg, &config.vcd_path)?;
+    lap("Sim data");
+
+    let passes = PassManager::from_config(Some(&config.conversion_passes), &[])?;
+    let source_files = read_source_files(dpdg.vertices.iter().map(|v| v.file.as_str()));
+    let options = ConversionOptions { verbose_name: false, is_dpdg: true, source_files };
+    let mut chisel = passes.run(dpdg.clone(), &options);
+
+    for v in dpdg.vertices.iter_mut().chain(chisel.vertices.iter_mut()) {
+        v.timestamp -= INITIAL_STATE_TIMESTAMP;
+        for (t, _) in v.sim_history.iter_mut().flat_map(|h| h.window.iter_mut()) {
+            *t -= INITIAL_STATE_TIMESTAMP;
+        }
+    }
+    if config.golden_values.is_some() {
+        mark_earliest_mismatch(&mut dpdg);
+        mark_earliest_mismatch(&mut chisel);
+    }
+    lap("Conversion");
+
+    Ok(Trace { firrtl: dpdg, chisel, source_files: options.source_files, timings })
+}
+
+/// Collapses all nodes in submodules of the top module into one node per instance and timestamp, like the initial
+/// view of the viewer with hierarchical grouping enabled. Edges between collapsed nodes are redirected and deduplicated.
+pub fn group_by_module(pdg: &ExportablePDG) -> ExportablePDG {
+    let mut vertices = vec![];
+    let mut groups = HashMap::new();
+    let mut new_index = vec![];
+    for v in &pdg.vertices {
+        let idx = match v.module_path.iter().find(|p| !p.is_empty()) {
+            Some(instance) => *groups.entry((v.timestamp, instance.clone())).or_insert_with(|| {
+                vertices.push(module_node(instance, v.timestamp));
+                vertices.len() - 1
+            }),
+            None => {
+                vertices.push(v.clone());
+                vertices.len() - 1
+            }
+        };
+        new_index.push(idx as u32);
+    }
+
+    let mut seen = HashSet::new();
+    let edges = pdg.edges.iter()
+        .map(|e| {
+            let mut e = e.clone();
+            e.from = new_index[e.from as usize];
+            e.to = new_index[e.to as usize];
+            e
+        })
+        .filter(|e| e.from != e.to && seen.insert(e.clone()))
+        .collect();
+
+    ExportablePDG { vertices, edges }
+}
+
+fn module_node(instance: &str, timestamp: i64) -> ExportablePDGNode {
+    ExportablePDGNode { file: "".into(), line: 0, char: 0, end_char: None, name: format!("module_{}", instance), kind: PDGSpecNodeKind::Definition,
+        clocked: false, module_path: vec![instance.to_string()], related_signal: None, assigns_to: None, predicate: None, provenance: vec![],
+        sim_data: None, sim_history: None, golden_mismatch: None, timestamp, is_chisel_assignment: false }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use crate::{graph::AdjacencyPDG, pdg_spec::{PDGSpecEdgeKind, PDGSpecNodeKind}};
+
+/// The Chisel constructs that take a predicate. `when` also matches `.elsewhen`.
+const PREDICATE_KEYWORDS: [&str; 1] = ["when"];
+
+/// Reconstructs the predicate expressions of all control flow vertices and stores them in the `predicate` field.
+/// The expression is taken from the source code if it is in `source_files`. Otherwise, only the signals it reads are known.
+/// Conditionals on anonymous `_T` FIRRTL signals are also renamed after their predicate, as their own name is meaningless.
+pub fn reconstruct_predicates(mut graph: AdjacencyPDG, source_files: &HashMap<String, Vec<String>>) -> AdjacencyPDG {
+    let mut predicates = vec![];
+    for (id, vert) in graph.vertices.iter().enumerate() {
+        if vert.kind != PDGSpecNodeKind::ControlFlow {
+            continue;
+        }
+
+        let operands = predicate_operands(&graph, id as u32);
+        let source_line = vert.line.checked_sub(1)
+            .and_then(|l| source_files.get(&vert.file)?.get(l as usize));
+        let from_source = source_line.and_then(|line| {
+            // Without a column range, we cannot tell which predicate on the line belongs to this vertex
+            let column = vert.end_char.map(|_| vert.char);
+            predicate_from_source(line, column, &operands)
+        });
+        let Some(predicate) = from_source.or_else(|| predicate_from_operands(&operands)) else {
+            continue;
+        };
+
+        let is_anonymous = vert.related_signal.as_ref()
+            .and_then(|s| s.signal_path.split(".").last())
+            .is_some_and(|name| name.starts_with("pred__T"));
+        predicates.push((id, predicate, is_anonymous));
+    }
+
+    for (id, predicate, is_anonymous) in predicates {
+        if is_anonymous {
+            graph.vertices[id].name = predicate.clone();
+        }
+        graph.vertices[id].predicate = Some(predicate);
+    }
+
+    graph
+}
+
+/// The names of the signals that the predicate of a control flow vertex reads.
+fn predicate_operands(graph: &AdjacencyPDG, vertex: u32) -> Vec<String> {
+    graph.outgoing(vertex)
+        .filter(|e| e.kind == PDGSpecEdgeKind::Data)
+        .filter_map(|e| {
+            let data_signal = graph.vertices[e.to as usize].related_signal.as_ref()?;
+            let data_signal_name = data_signal.signal_path.split(".").last()?;
+            if data_signal.field_path.is_empty() {
+                Some(data_signal_name.to_string())
+            } else {
+                Some(format!("{}.{}", data_signal_name, data_signal.field_path))
+            }
+        })
+        .filter(|name| !name.starts_with("_"))
+        .collect()
+}
+
+/// Extracts the predicate of a `when` or `.elsewhen` on a line of Chisel code. If a column is given, the first predicate
+/// at or after that column is used. Otherwise, the first predicate that mentions all operands is preferred.
+pub fn predicate_from_source(line: &str, column: Option<u32>, operands: &[String]) -> Option<String> {
+    let start = column.map_or(0, |c| (c as usize).saturating_sub(1)).min(line.len());
+    let candidates = predicate_arguments(line.get(start..)?);
+    if column.is_some() {
+        return candidates.into_iter().next();
+    }
+
+    let mentions_all = |pred: &String| operands.iter().all(|op| {
+        // Operands can be fields of bundles, of which only the last part is guaranteed to appear in the source
+        op.split(".").last().is_some_and(|field| pred.contains(field))
+    });
+    candidates.iter().find(|pred| mentions_all(pred)).or(candidates.first()).cloned()
+}
+
+/// Finds the arguments of all predicate keywords on a line.
+fn predicate_arguments(line: &str) -> Vec<String> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut arguments = vec![];
+    for keyword in PREDICATE_KEYWORDS {
+        for (pos, _) in line.match_indices(keyword) {
+            // The keyword must be a separate identifier. `elsewhen` is allowed, as it is the same construct.
+            let before = &line[..pos];
+            let before = before.strip_suffix("else").unwrap_or(before);
+            if before.chars().last().is_some_and(is_ident) {
+                continue;
+            }
+            let rest = line[pos + keyword.len()..].trim_start();
+            if let Some(argument) = balanced_argument(rest) {
+                arguments.push(argument);
+            }
+        }
+    }
+    arguments
+}
+
+/// Returns the contents of the parenthesised expression at the start of `s`, if it is complete.
+fn balanced_argument(s: &str) -> Option<String> {
+    if !s.starts_with('(') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut in_string = false;
+    for (idx, c) in s.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    let argument = s[1..idx].split_whitespace().collect::<Vec<_>>().join(" ");
+                    return (!argument.is_empty()).then_some(argument);
+                }
+            }
+            _ => ()
+        }
+    }
+    None
+}
+
+/// Describes a predicate by its operands if the source code is not available. The operators are unknown at this point.
+fn predicate_from_operands(operands: &[String]) -> Option<String> {
+    (!operands.is_empty()).then(|| format!("<predicate over {}>", operands.join(", ")))
+}
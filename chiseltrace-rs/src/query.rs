@@ -0,0 +1,104 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+
+use crate::{pdg_spec::{ExportablePDG, ExportablePDGNode, SimValue}, value_format::signal_key};
+
+/// The maps from timestamps to nodes, and from nodes to their outgoing and incoming edges
+pub type LookupTables = (HashMap<i64, Vec<usize>>, HashMap<u32, Vec<usize>>, HashMap<u32, Vec<usize>>);
+
+/// Creates the maps from timestamps to nodes, and from nodes to their outgoing and incoming edges.
+pub fn build_lookup_tables(dpdg: &ExportablePDG) -> LookupTables {
+    let mut time_to_nodes = HashMap::new();
+    for (idx, v) in dpdg.vertices.iter().enumerate() {
+        time_to_nodes.entry(v.timestamp).and_modify(|nodes: &mut Vec<usize>| nodes.push(idx)).or_insert(vec![idx]);
+    }
+
+    let mut dep_to_edges = HashMap::new();
+    for (idx, e) in dpdg.edges.iter().enumerate() {
+        dep_to_edges.entry(e.from).and_modify(|edges: &mut Vec<usize>| edges.push(idx)).or_insert(vec![idx]);
+    }
+
+    let mut prov_to_edges = HashMap::new();
+    for (idx, e) in dpdg.edges.iter().enumerate() {
+        prov_to_edges.entry(e.to).and_modify(|edges: &mut Vec<usize>| edges.push(idx)).or_insert(vec![idx]);
+    }
+
+    (time_to_nodes, dep_to_edges, prov_to_edges)
+}
+
+/// The nodes that can be reached from a node by following its dependencies, including the node itself
+pub fn reachable_from(dpdg: &ExportablePDG, dep_to_edges: &HashMap<u32, Vec<usize>>, id: usize) -> HashSet<usize> {
+    let mut nodes_reached = HashSet::new();
+
+    let mut stack = vec![id];
+    while let Some(node_idx) = stack.pop() {
+        nodes_reached.insert(node_idx);
+        if let Some(edges) = dep_to_edges.get(&(node_idx as u32)) {
+            for edge_idx in edges {
+                let edge = &dpdg.edges[*edge_idx];
+                if !nodes_reached.contains(&(edge.to as usize)) {
+                    stack.push(edge.to as usize);
+                }
+            }
+        }
+    }
+
+    nodes_reached
+}
+
+/// The shortest chain of dependencies from one node to another, including both nodes
+pub fn dependency_path(dpdg: &ExportablePDG, dep_to_edges: &HashMap<u32, Vec<usize>>, from: usize, to: usize) -> Option<Vec<usize>> {
+    let mut previous = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    previous.insert(from, from);
+    while let Some(node_idx) = queue.pop_front() {
+        if node_idx == to {
+            let mut path = vec![to];
+            let mut current = to;
+            while current != from {
+                current = previous[&current];
+                path.push(current);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for edge_idx in dep_to_edges.get(&(node_idx as u32)).into_iter().flatten() {
+            let next = dpdg.edges[*edge_idx].to as usize;
+            if let Entry::Vacant(entry) = previous.entry(next) {
+                entry.insert(node_idx);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// Whether a node belongs to a signal, given as its path (e.g. `io.out`), its path and field (e.g. `io.out.bits`),
+/// or the name it assigns to
+pub fn refers_to_signal(node: &ExportablePDGNode, signal: &str) -> bool {
+    node.related_signal.as_ref().is_some_and(|s| s.signal_path == signal || signal_key(s) == signal)
+        || node.assigns_to.as_deref() == Some(signal)
+}
+
+/// The nodes of a signal, optionally only those at a timestamp
+pub fn signal_nodes(dpdg: &ExportablePDG, signal: &str, timestamp: Option<i64>) -> Vec<usize> {
+    dpdg.vertices.iter()
+        .enumerate()
+        .filter(|(_, v)| refers_to_signal(v, signal) && timestamp.is_none_or(|t| v.timestamp == t))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// The value of a signal at a timestamp. If none of the nodes of the signal are at that timestamp,
+/// the value is looked up in the value history of the other nodes, if it was attached.
+pub fn signal_value(dpdg: &ExportablePDG, signal: &str, timestamp: i64) -> Option<SimValue> {
+    let nodes = signal_nodes(dpdg, signal, None);
+    let at_timestamp = nodes.iter()
+        .map(|idx| &dpdg.vertices[*idx])
+        .filter(|v| v.timestamp == timestamp)
+        .find_map(|v| v.sim_data.clone());
+    at_timestamp.or_else(|| nodes.iter()
+        .filter_map(|idx| dpdg.vertices[*idx].sim_history.as_ref())
+        .flat_map(|h| h.window.iter())
+        .find(|(t, _)| *t == timestamp)
+        .map(|(_, v)| v.clone()))
+}
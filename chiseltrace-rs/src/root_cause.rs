@@ -0,0 +1,258 @@
+use std::{collections::{hash_map::Entry, HashMap, VecDeque}, fmt::Display};
+
+use serde::Serialize;
+
+use crate::{golden::{same_bits, GoldenValues}, pdg_spec::{ExportablePDG, PDGSpecEdgeKind, PDGSpecNodeKind}, query::build_lookup_tables, value_format::ValueFormat};
+
+/// Why a node is a root cause candidate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "camelCase")]
+pub enum RootCauseReason {
+    /// The first node on its data path with a value that differs from the golden value
+    GoldenDivergence { expected: String, actual: String },
+    /// The earliest node on its data path that has the same value as the criterion
+    ValueOrigin,
+    /// A control flow node that the wrong value depends on, which evaluated differently in its previous activation
+    ControlFlip { previous: String, current: String },
+    /// A primary input of the design that the wrong value depends on
+    PrimaryInput
+}
+
+impl RootCauseReason {
+    /// How strongly the reason points at a root cause
+    fn weight(&self) -> u32 {
+        match self {
+            RootCauseReason::GoldenDivergence { .. } => 8,
+            RootCauseReason::ValueOrigin => 4,
+            RootCauseReason::ControlFlip { .. } => 3,
+            RootCauseReason::PrimaryInput => 2
+        }
+    }
+}
+
+impl Display for RootCauseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dec = |bits: &str| ValueFormat::Dec.format_bits(bits);
+        match self {
+            RootCauseReason::GoldenDivergence { expected, actual } =>
+                write!(f, "first divergence from the golden value (expected {}, got {})", dec(expected), dec(actual)),
+            RootCauseReason::ValueOrigin => write!(f, "earliest node with the wrong value"),
+            RootCauseReason::ControlFlip { previous, current } =>
+                write!(f, "control decision flipped from {} to {}", dec(previous), dec(current)),
+            RootCauseReason::PrimaryInput => write!(f, "primary input")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RootCauseCandidate {
+    pub node: usize,
+    pub score: u32,
+    /// The number of dependencies between the criterion and the node
+    pub distance: u32,
+    pub reasons: Vec<RootCauseReason>
+}
+
+/// The node that a DPDG was traced from: the latest node that no other node depends on
+pub fn find_criterion(pdg: &ExportablePDG) -> Option<usize> {
+    let (_, _, prov_to_edges) = build_lookup_tables(pdg);
+    pdg.vertices.iter()
+        .enumerate()
+        .filter(|(idx, _)| !prov_to_edges.contains_key(&(*idx as u32)))
+        .min_by_key(|(idx, v)| (-v.timestamp, *idx))
+        .map(|(idx, _)| idx)
+}
+
+/// Ranks the nodes that the value of the criterion depends on by how likely they are to have caused it, assuming
+/// the value is wrong. The data dependencies are followed back from the criterion. Within them, the candidates are
+/// the earliest nodes that carry the same value as the criterion, the first nodes that diverge from the golden values
+/// (or from the golden reference that the DPDG was annotated with), the primary inputs, and the control flow nodes of the
+/// data path that evaluated differently than before.
+pub fn rank_root_causes(pdg: &ExportablePDG, criterion: usize, golden: Option<&GoldenValues>) -> Vec<RootCauseCandidate> {
+    let (_, dep_to_edges, _) = build_lookup_tables(pdg);
+    let edges_of = |idx: usize| dep_to_edges.get(&(idx as u32)).into_iter().flatten().map(|e| &pdg.edges[*e]);
+    let data_deps = |idx: usize| edges_of(idx)
+        .filter(|e| matches!(e.kind, PDGSpecEdgeKind::Data | PDGSpecEdgeKind::Index))
+        .map(|e| e.to as usize);
+
+    // The data cone of the criterion, with the distance of each node to the criterion
+    let mut distances = HashMap::from([(criterion, 0u32)]);
+    let mut queue = VecDeque::from([criterion]);
+    while let Some(idx) = queue.pop_front() {
+        let distance = distances[&idx] + 1;
+        for dep in data_deps(idx) {
+            if let Entry::Vacant(entry) = distances.entry(dep) {
+                entry.insert(distance);
+                queue.push_back(dep);
+            }
+        }
+    }
+
+    // The control flow nodes that decided whether the statements in the data cone were active
+    let mut control_distances: HashMap<usize, u32> = HashMap::new();
+    for (idx, distance) in &distances {
+        for e in edges_of(*idx).filter(|e| e.kind == PDGSpecEdgeKind::Conditional) {
+            let to = e.to as usize;
+            if pdg.vertices[to].kind == PDGSpecNodeKind::ControlFlow && !distances.contains_key(&to) {
+                let d = control_distances.entry(to).or_insert(distance + 1);
+                *d = (*d).min(distance + 1);
+            }
+        }
+    }
+
+    let bits = |idx: usize| pdg.vertices[idx].sim_data.as_ref().map(|v| v.bits.as_str());
+    let target = bits(criterion);
+    let carries_target = |idx: usize| matches!((target, bits(idx)), (Some(t), Some(b)) if same_bits(t, b));
+    let divergence = |idx: usize| {
+        let node = &pdg.vertices[idx];
+        let expected = golden.and_then(|g| g.expected(node)).or(node.golden_mismatch.as_ref().map(|m| m.expected.as_str().into()))?;
+        let actual = bits(idx)?;
+        (!same_bits(&expected, actual)).then(|| (expected.into_owned(), actual.to_string()))
+    };
+
+    let mut candidates = vec![];
+    for (&idx, &distance) in &distances {
+        let mut reasons = vec![];
+        if let Some((expected, actual)) = divergence(idx) && !data_deps(idx).any(|d| divergence(d).is_some()) {
+            reasons.push(RootCauseReason::GoldenDivergence { expected, actual });
+        }
+        if carries_target(idx) && !data_deps(idx).any(carries_target) {
+            reasons.push(RootCauseReason::ValueOrigin);
+        }
+        if pdg.vertices[idx].kind == PDGSpecNodeKind::IO && data_deps(idx).next().is_none() {
+            reasons.push(RootCauseReason::PrimaryInput);
+        }
+        candidates.push(RootCauseCandidate { node: idx, score: 0, distance, reasons });
+    }
+    let activations = control_activations(pdg);
+    for (&idx, &distance) in &control_distances {
+        let mut reasons = vec![];
+        if let Some((expected, actual)) = divergence(idx) {
+            reasons.push(RootCauseReason::GoldenDivergence { expected, actual });
+        }
+        if let Some((previous, current)) = control_flip(pdg, &activations, idx) {
+            reasons.push(RootCauseReason::ControlFlip { previous, current });
+        }
+        candidates.push(RootCauseCandidate { node: idx, score: 0, distance, reasons });
+    }
+
+    candidates.retain(|c| !c.reasons.is_empty());
+    for c in &mut candidates {
+        c.score = c.reasons.iter().map(|r| r.weight()).sum();
+    }
+    // Earlier nodes are more likely to be the cause than the nodes they affected
+    candidates.sort_by_key(|c| (std::cmp::Reverse(c.score), pdg.vertices[c.node].timestamp, std::cmp::Reverse(c.distance), c.node));
+    candidates
+}
+
+/// The activations of the control flow statements, by name and module path, ordered by timestamp
+type Activations<'a> = HashMap<(&'a str, &'a [String]), Vec<usize>>;
+
+fn control_activations(pdg: &ExportablePDG) -> Activations<'_> {
+    let mut activations: Activations = HashMap::new();
+    for (idx, v) in pdg.vertices.iter().enumerate().filter(|(_, v)| v.kind == PDGSpecNodeKind::ControlFlow) {
+        activations.entry((v.name.as_str(), v.module_path.as_slice())).or_default().push(idx);
+    }
+    for nodes in activations.values_mut() {
+        nodes.sort_by_key(|idx| pdg.vertices[*idx].timestamp);
+    }
+    activations
+}
+
+/// The previous and current value of a control flow node, if it evaluated differently in its previous activation, which
+/// is the latest earlier activation of the same statement in the DPDG. The value history is not used, as the previous
+/// cycle is not necessarily an activation.
+fn control_flip(pdg: &ExportablePDG, activations: &Activations, idx: usize) -> Option<(String, String)> {
+    let node = &pdg.vertices[idx];
+    let current = &node.sim_data.as_ref()?.bits;
+    let statement = activations.get(&(node.name.as_str(), node.module_path.as_slice()))?;
+    let earlier = statement.partition_point(|i| pdg.vertices[*i].timestamp < node.timestamp);
+    let previous = &pdg.vertices[statement[earlier.checked_sub(1)?]].sim_data.as_ref()?.bits;
+    (!same_bits(previous, current)).then(|| (previous.clone(), current.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pdg_spec::{ExportablePDGEdge, ExportablePDGNode, GoldenMismatch, SimValue, SimValueKind};
+
+    use super::*;
+
+    fn node(name: &str, kind: PDGSpecNodeKind, timestamp: i64, bits: &str) -> ExportablePDGNode {
+        let sim_data = SimValue { type_name: None, width: bits.len() as u32, bits: bits.into(), signed: false, kind: SimValueKind::Ground,
+            enum_variant: None, binary_point: None, fields: vec![], display: None };
+        ExportablePDGNode { file: "Top.scala".into(), line: 0, char: 0, end_char: None, name: name.into(), kind, clocked: false,
+            module_path: vec![], related_signal: None, assigns_to: None, predicate: None, provenance: vec![], sim_data: Some(sim_data),
+            sim_history: None, golden_mismatch: None, timestamp, is_chisel_assignment: true }
+    }
+
+    fn edge(from: u32, to: u32, kind: PDGSpecEdgeKind) -> ExportablePDGEdge {
+        ExportablePDGEdge { from, to, kind, clocked: false }
+    }
+
+    fn ranked_nodes(pdg: &ExportablePDG) -> Vec<usize> {
+        let criterion = find_criterion(pdg).unwrap();
+        rank_root_causes(pdg, criterion, None).iter().map(|c| c.node).collect()
+    }
+
+    #[test]
+    fn the_earliest_node_with_the_wrong_value_is_the_origin() {
+        // in -> a -> b -> out, where a computes the value 5 that reaches the output
+        let pdg = ExportablePDG {
+            vertices: vec![
+                node("in", PDGSpecNodeKind::IO, 0, "0011"),
+                node("a", PDGSpecNodeKind::Connection, 1, "0101"),
+                node("b", PDGSpecNodeKind::Connection, 2, "0101"),
+                node("out", PDGSpecNodeKind::Connection, 3, "0101"),
+            ],
+            edges: vec![edge(3, 2, PDGSpecEdgeKind::Data), edge(2, 1, PDGSpecEdgeKind::Data), edge(1, 0, PDGSpecEdgeKind::Data)]
+        };
+        let ranking = rank_root_causes(&pdg, 3, None);
+        assert_eq!(ranking.iter().map(|c| c.node).collect::<Vec<_>>(), vec![1, 0]);
+        assert_eq!(ranking[0].reasons, vec![RootCauseReason::ValueOrigin]);
+        assert_eq!(ranking[0].distance, 2);
+        assert_eq!(ranking[1].reasons, vec![RootCauseReason::PrimaryInput]);
+    }
+
+    #[test]
+    fn the_first_golden_divergence_ranks_highest() {
+        // in -> a -> b -> out, where a and b differ from the golden model, but only a diverges first
+        let mut pdg = ExportablePDG {
+            vertices: vec![
+                node("in", PDGSpecNodeKind::IO, 0, "0011"),
+                node("a", PDGSpecNodeKind::Connection, 1, "0100"),
+                node("b", PDGSpecNodeKind::Connection, 2, "0101"),
+                node("out", PDGSpecNodeKind::Connection, 3, "0101"),
+            ],
+            edges: vec![edge(3, 2, PDGSpecEdgeKind::Data), edge(2, 1, PDGSpecEdgeKind::Data), edge(1, 0, PDGSpecEdgeKind::Data)]
+        };
+        pdg.vertices[1].golden_mismatch = Some(GoldenMismatch { expected: "0110".into(), earliest: false });
+        pdg.vertices[2].golden_mismatch = Some(GoldenMismatch { expected: "0111".into(), earliest: false });
+
+        let ranking = rank_root_causes(&pdg, 3, None);
+        assert_eq!(ranking[0].node, 1);
+        assert_eq!(ranking[0].reasons, vec![RootCauseReason::GoldenDivergence { expected: "0110".into(), actual: "0100".into() }]);
+        // b is still the origin of the wrong value, but ranks below the divergence
+        assert_eq!(ranking[1].node, 2);
+        assert_eq!(ranking[1].reasons, vec![RootCauseReason::ValueOrigin]);
+    }
+
+    #[test]
+    fn control_decisions_that_flipped_are_candidates() {
+        // Two activations of `when (en)`, of which the second one decides that out is assigned
+        let pdg = ExportablePDG {
+            vertices: vec![
+                node("en", PDGSpecNodeKind::ControlFlow, 1, "0"),
+                node("en", PDGSpecNodeKind::ControlFlow, 2, "1"),
+                node("in", PDGSpecNodeKind::IO, 2, "0011"),
+                node("out", PDGSpecNodeKind::Connection, 3, "0011"),
+            ],
+            edges: vec![edge(3, 1, PDGSpecEdgeKind::Conditional), edge(3, 2, PDGSpecEdgeKind::Data)]
+        };
+        let ranking = rank_root_causes(&pdg, 3, None);
+        let flip = ranking.iter().find(|c| c.node == 1).unwrap();
+        assert_eq!(flip.reasons, vec![RootCauseReason::ControlFlip { previous: "0".into(), current: "1".into() }]);
+        assert_eq!(flip.distance, 1);
+        // The first activation has nothing to compare with
+        assert!(!ranked_nodes(&pdg).contains(&0));
+    }
+}
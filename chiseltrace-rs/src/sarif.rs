@@ -0,0 +1,173 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::pdg_spec::ExportableSliceStatement;
+
+const SARIF_VERSION: &str = "2.1.0";
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+const STATIC_SLICE_RULE: &str = "chiseltrace/static-slice";
+const DYNAMIC_SLICE_RULE: &str = "chiseltrace/dynamic-slice";
+
+/// A minimal subset of the SARIF 2.1.0 log format. Only the parts that are needed to mark statements in
+/// source files are modelled here, which is enough for editors and code review tools to highlight slices inline.
+#[derive(Debug, Serialize)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    pub version: String,
+    pub runs: Vec<SarifRun>
+}
+
+#[derive(Debug, Serialize)]
+pub struct SarifRun {
+    pub tool: SarifTool,
+    pub results: Vec<SarifResult>
+}
+
+#[derive(Debug, Serialize)]
+pub struct SarifTool {
+    pub driver: SarifDriver
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifDriver {
+    pub name: String,
+    pub version: String,
+    pub information_uri: String,
+    pub rules: Vec<SarifRule>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifRule {
+    pub id: String,
+    pub short_description: SarifMessage
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: String,
+    pub level: String,
+    pub message: SarifMessage,
+    pub locations: Vec<SarifLocation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<SarifResultProperties>
+}
+
+#[derive(Debug, Serialize)]
+pub struct SarifMessage {
+    pub text: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifLocation {
+    pub physical_location: SarifPhysicalLocation
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifPhysicalLocation {
+    pub artifact_location: SarifArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<SarifRegion>
+}
+
+#[derive(Debug, Serialize)]
+pub struct SarifArtifactLocation {
+    pub uri: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifRegion {
+    pub start_line: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_column: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<u32>
+}
+
+/// Tool-specific data attached to a result. For dynamic slices, this holds the cycles in which the statement contributed.
+#[derive(Debug, Serialize)]
+pub struct SarifResultProperties {
+    pub cycles: Vec<i64>
+}
+
+impl SarifLog {
+    /// Creates a SARIF log with one result per statement of a static slice.
+    pub fn from_static_slice(statements: &[ExportableSliceStatement]) -> Self {
+        let results = statements.iter().map(|stmt| {
+            SarifResult {
+                rule_id: STATIC_SLICE_RULE.into(),
+                level: "note".into(),
+                message: SarifMessage { text: "Statement is part of the static slice".into() },
+                locations: vec![SarifLocation::from(stmt)],
+                properties: None
+            }
+        }).collect::<Vec<_>>();
+
+        Self::with_results(results)
+    }
+
+    /// Creates a SARIF log with one result per statement of a dynamic slice. Every statement is accompanied
+    /// by the cycles at which it contributed to the slicing criterion.
+    pub fn from_dynamic_slice(statements: &[(ExportableSliceStatement, BTreeSet<i64>)]) -> Self {
+        let results = statements.iter().map(|(stmt, cycles)| {
+            let cycle_list = cycles.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", ");
+            SarifResult {
+                rule_id: DYNAMIC_SLICE_RULE.into(),
+                level: "note".into(),
+                message: SarifMessage { text: format!("Statement contributed to the dynamic slice at cycle(s) {}", cycle_list) },
+                locations: vec![SarifLocation::from(stmt)],
+                properties: Some(SarifResultProperties { cycles: cycles.iter().copied().collect() })
+            }
+        }).collect::<Vec<_>>();
+
+        Self::with_results(results)
+    }
+
+    fn with_results(results: Vec<SarifResult>) -> Self {
+        let rules = vec![
+            SarifRule { id: STATIC_SLICE_RULE.into(), short_description: SarifMessage { text: "Statement in static program slice".into() } },
+            SarifRule { id: DYNAMIC_SLICE_RULE.into(), short_description: SarifMessage { text: "Statement in dynamic program slice".into() } },
+        ];
+
+        SarifLog {
+            schema: SARIF_SCHEMA.into(),
+            version: SARIF_VERSION.into(),
+            runs: vec![SarifRun {
+                tool: SarifTool {
+                    driver: SarifDriver {
+                        name: "ChiselTrace".into(),
+                        version: env!("CARGO_PKG_VERSION").into(),
+                        information_uri: "https://github.com/jarlb/chiseltrace".into(),
+                        rules
+                    }
+                },
+                results
+            }]
+        }
+    }
+}
+
+impl From<&ExportableSliceStatement> for SarifLocation {
+    fn from(value: &ExportableSliceStatement) -> Self {
+        // SARIF lines and columns are 1-based. A value of 0 means that the source locator did not contain the information.
+        let region = (value.line > 0).then(|| SarifRegion {
+            start_line: value.line,
+            start_column: (value.char > 0).then_some(value.char),
+            end_column: value.end_char.filter(|_| value.char > 0)
+        });
+        SarifLocation {
+            physical_location: SarifPhysicalLocation {
+                artifact_location: SarifArtifactLocation { uri: value.file.clone() },
+                region
+            }
+        }
+    }
+}
@@ -1,17 +1,30 @@
 /*
     Note: this file contains some copied (slightly modified) code from the tywaves translator in the surfer-tywaves repository 
 */
-use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
+use std::{collections::HashMap, fs::{self, File}, io::{BufReader, BufWriter}, path::Path};
 
-use tywaves_rs::{hgldd, tyvcd::{builder::{GenericBuilder, TyVcdBuilder}, spec::{Variable, VariableKind}, trace_pointer::TraceFinder}};
+use tywaves_rs::{hgldd, tyvcd::{builder::{GenericBuilder, TyVcdBuilder}, spec::{TyVcd, TypeInfo, Variable, VariableKind}, trace_pointer::TraceFinder}};
 use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
 use vcd::{Command, IdCode};
 
-use crate::{errors::Error, pdg_spec::{ExportablePDG, ExportablePDGNode}};
+use crate::{errors::Error, golden::{annotate_mismatches, GoldenValues}, graphbuilder::INITIAL_STATE_TIMESTAMP, pdg_spec::{ExportablePDG, ExportablePDGNode, PDGSpecRelatedSignal, SimHistory, SimValue, SimValueKind}, util};
+
+/// Increase when the layout of the cached HGLDD changes, e.g. after updating tywaves-rs
+const MODEL_CACHE_VERSION: u32 = 1;
 
 pub struct TywavesInterface {
-    builder: TyVcdBuilder<hgldd::spec::Hgldd>,
-    top_module: String
+    tyvcd: TyVcd,
+    top_module: String,
+    /// Hash of everything the Tywaves model is built from, part of the cache key of rewritten VCDs
+    model_key: String,
+    /// The scopes of the testbench harness that contain the DUT, such as ["TOP", "svsimTestbench", "dut"]
+    extra_scopes: Vec<String>,
+    /// The number of cycles before and after each node for which the values are attached, if enabled
+    history_window: Option<u32>,
+    /// The expected values that the injected values are checked against, if any
+    golden: Option<GoldenValues>
 }
 
 // Essentially the Surfer value kinds, but with some types removed, such as high impedance
@@ -43,38 +56,107 @@ pub enum VariableInfo {
 
 /// An interface to Tywaves that is based on the one available in the surfer-tywaves project
 impl TywavesInterface {
+    /// Builds the Tywaves model of the design. Only the parsing of the HGLDD files is cached: the parsed files are kept
+    /// in the user's cache directory, so they are only parsed again when they change, but the model is built from them
+    /// for the given scopes on every call.
     pub fn new(hgldd_dir: &Path, extra_scopes: Vec<String>, top_module: &String) -> Result<Self> {
-        let hgldd = hgldd::reader::parse_hgldd_dir(hgldd_dir)
-            .map_err(Error::from)?;
+        let hgldd_key = Self::hgldd_cache_key(hgldd_dir)?;
+        let hgldd_path = util::cache_dir()?.join(format!("{}.hgldd", hgldd_key));
+
+        let hgldd = match load_cached(&hgldd_path) {
+            Some(hgldd) => hgldd,
+            None => {
+                let hgldd = hgldd::reader::parse_hgldd_dir(hgldd_dir)
+                    .map_err(Error::from)?;
+                // Failing to cache the HGLDD only costs time in the next session
+                if let Err(e) = store_cached(&hgldd_path, &hgldd) {
+                    println!("Could not cache the parsed HGLDD: {}", e);
+                }
+                hgldd
+            }
+        };
         let mut builder = TyVcdBuilder::init(hgldd)
-            .with_extra_artifact_scopes(extra_scopes, top_module);
+            .with_extra_artifact_scopes(extra_scopes.clone(), top_module);
         builder.build().map_err(Error::from)?;
-        Ok(Self { builder, top_module: top_module.clone() })
+        let tyvcd = builder.get_copy().ok_or(Error::TyVCDMissing)?;
+        let model_key = Self::model_key(&hgldd_key, &extra_scopes, top_module);
+        Ok(Self { tyvcd, top_module: top_module.clone(), model_key, extra_scopes, history_window: None, golden: None })
+    }
+
+    /// The key under which the parsed HGLDD is cached. It only covers the HGLDD files, which is all that is parsed.
+    fn hgldd_cache_key(hgldd_dir: &Path) -> Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(MODEL_CACHE_VERSION.to_le_bytes());
+        util::hash_path(&mut hasher, hgldd_dir)?;
+        Ok(util::hex_digest(hasher))
+    }
+
+    /// Hash of the parsed HGLDD and the scopes the Tywaves model is built for
+    fn model_key(hgldd_key: &str, extra_scopes: &[String], top_module: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(hgldd_key.as_bytes());
+        hasher.update(top_module.as_bytes());
+        for scope in extra_scopes {
+            hasher.update(b"/");
+            hasher.update(scope.as_bytes());
+        }
+        util::hex_digest(hasher)
     }
 
+    /// Also attach the previous value of each node's signal and its values in the given number of cycles around the node.
+    pub fn with_value_history(mut self, window: u32) -> Self {
+        self.history_window = Some(window);
+        self
+    }
+
+    /// Also check the injected values against the values expected by a golden reference, and annotate mismatches.
+    pub fn with_golden_values(mut self, golden: GoldenValues) -> Self {
+        self.golden = Some(golden);
+        self
+    }
+
+    /// Rewrites the VCD file such that it contains the Tywaves type information and returns the path of the result.
+    /// Rewritten VCDs are kept in the user's cache directory, so an unchanged trace is only rewritten once.
     pub fn vcd_rewrite(&self, vcd_path: &Path) -> Result<String> {
-        let tywaves_scopes = &self.builder.get_ref().unwrap().scopes;
+        let cached_path = util::cache_dir()?.join(format!("{}-{}.vcd", self.top_module, self.rewrite_cache_key(vcd_path)?));
+        if cached_path.exists() {
+            return Ok(cached_path.to_string_lossy().into_owned());
+        }
+
+        let tywaves_scopes = &self.tyvcd.scopes;
         // Get the list of scopes
         let scopes_def_list = tywaves_scopes
             .iter()
             .map(|(_, v)| (v.read().unwrap().clone()))
             .collect();
 
+        // The VCD is first rewritten to a file that is unique to this process and then moved in place,
+        // so parallel sessions never pick up a partially written VCD.
+        let partial_path = cached_path.with_extension(format!("{}.partial", std::process::id()));
         let mut vcd_rewriter = tywaves_rs::vcd_rewrite::VcdRewriter::new(
             vcd_path,
             scopes_def_list,
-            format!("{}.vcd", self.top_module),
+            partial_path.to_string_lossy().into_owned(),
         )
         .map_err(Error::from)?;
         
         vcd_rewriter
             .rewrite()
             .map_err(Error::from)?;
-        Ok(vcd_rewriter.get_final_file().clone())
+        fs::rename(vcd_rewriter.get_final_file(), &cached_path)?;
+        Ok(cached_path.to_string_lossy().into_owned())
+    }
+
+    /// The key under which the rewritten version of a VCD is cached. It covers everything the rewrite depends on.
+    fn rewrite_cache_key(&self, vcd_path: &Path) -> Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.model_key.as_bytes());
+        util::hash_path(&mut hasher, vcd_path)?;
+        Ok(util::hex_digest(hasher))
     }
 
     pub fn find_signal(&self, path: &[String]) -> Result<Variable> {
-        let trace_getter = self.builder.get_ref().unwrap().find_trace(path).ok_or(Error::TywavesSignalNotFound)?;
+        let trace_getter = self.tyvcd.find_trace(path).ok_or(Error::TywavesSignalNotFound)?;
         let binding = trace_getter.read().unwrap();
         let signal = binding.as_any().downcast_ref::<Variable>().ok_or(Error::TywavesDowncastFailed)?;
         Ok(signal.clone())
@@ -100,37 +182,26 @@ impl TywavesInterface {
 
     /// A version of translate_variable that does not translate the entire variable (like in surfer),
     /// but instead traverses the variable tree while translating, saving a lot of string processing.
+    /// Once the field path is exhausted, the remaining (sub)variable is translated including all of its fields.
     fn translate_variable_field(
         &self,
         variable: &Variable,
         raw_val_vcd: &str,
         field_path: &[&str],
         last_type: Option<&String>
-    ) -> Option<String> {
-        // Create the value representation
-        let render_fn = |_num_bits: u64, raw_val_vcd: &str| {
-            raw_val_vcd.to_string()
-        };
-
+    ) -> Option<SimValue> {
         match &variable.kind {
             // Ground value instantly translates to the raw bitvector value
-            VariableKind::Ground(_) => {
-                let prefix = if let Some(tpe) = last_type {format!("{} ", tpe)} else {"".into()};
-                // let mut prefix = variable.high_level_info.type_name.clone();
-                // if prefix.len() > 0 {
-                //     prefix = prefix + " ";
-                // }
-                Some(prefix + &variable.create_val_repr(raw_val_vcd, &render_fn))
-            },
+            VariableKind::Ground(_) => Some(self.translate_variable(variable, raw_val_vcd, last_type)),
             // Struct and vector get traversed using the field path
             VariableKind::Struct { fields } | VariableKind::Vector { fields } => {
                 let Some(field_str) = field_path.get(0) else {
-                    println!("Something has gone terribly wrong! (no field, but still struct left)");
-                    return None;
+                    return Some(self.translate_variable(variable, raw_val_vcd, last_type));
                 };
 
                 // Find the sub-field according to the path and get its value
-                let (mut field_val, mut _raw_val_vcd) = ("0", raw_val_vcd);
+                let raw_val_vcd = pad_bits(raw_val_vcd, variable.kind.find_width() as usize);
+                let (mut field_val, mut _raw_val_vcd) = ("0", raw_val_vcd.as_str());
                 let mut field_found = None;
                 for f in fields {
                     (field_val, _raw_val_vcd) = self.get_sub_raw_val(&f.kind, _raw_val_vcd);
@@ -153,174 +224,543 @@ impl TywavesInterface {
         }
     }
 
-    // To inject simulation data into the graph:
-    // We essentially want to associate simulation values with nodes.
-    // Due to the way the timestamps are set up, it should be possible to just grab the values from the 
-    // timestamps of the nodes. We do need a cache for if the values don't change
-    // 1) Read in a cycle of changes, update the cache
-    // 2) For each node, look up the base signal path in the VCD, then do a tywaves lookup using this value and
-    // select based on the field path
-    // 3) Add the information to the node
+    /// Translates a variable into a typed value, including all of its fields.
+    fn translate_variable(&self, variable: &Variable, raw_val_vcd: &str, last_type: Option<&String>) -> SimValue {
+        // Create the value representation
+        let render_fn = |_num_bits: u64, raw_val_vcd: &str| {
+            raw_val_vcd.to_string()
+        };
+
+        let width = variable.kind.find_width() as usize;
+        let bits = pad_bits(raw_val_vcd, width);
+        let fields = match &variable.kind {
+            VariableKind::Struct { fields } | VariableKind::Vector { fields } => {
+                let mut rest = bits.as_str();
+                fields.iter().map(|f| {
+                    let field_val;
+                    (field_val, rest) = self.get_sub_raw_val(&f.kind, rest);
+                    (f.name.clone(), self.translate_variable(f, field_val, Some(&f.high_level_info.type_name)))
+                }).collect()
+            }
+            _ => vec![]
+        };
+        let kind = match &variable.kind {
+            VariableKind::Struct { .. } => SimValueKind::Bundle,
+            VariableKind::Vector { .. } => SimValueKind::Vec,
+            _ => SimValueKind::Ground
+        };
+
+        // Tywaves renders ChiselEnum values as the name of their variant, using the enum definitions in the HGLDD.
+        // All other ground values are rendered by render_fn, which keeps the raw bits.
+        let enum_variant = if kind == SimValueKind::Ground {
+            Some(variable.create_val_repr(&bits, &render_fn)).filter(|repr| *repr != bits)
+        } else { None };
+
+        let info = &variable.high_level_info;
+        let type_name = last_type.or(Some(&info.type_name)).filter(|t| !t.is_empty()).cloned();
+        let signed = ["SInt", "FixedPoint", "Interval"].iter().any(|t| info.type_name.starts_with(t))
+            || last_type.is_some_and(|t| t.starts_with("SInt"));
+
+        SimValue {
+            type_name,
+            width: width as u32,
+            bits,
+            signed,
+            kind,
+            enum_variant,
+            binary_point: binary_point(info),
+            fields,
+            display: None
+        }
+    }
+
+    /// Injects the values from a VCD file that was rewritten by `vcd_rewrite` into the DPDG.
+    pub fn inject_sim_data(&self, pdg: &mut ExportablePDG, vcd_path: impl AsRef<Path>) -> Result<()> {
+        let mut translator = TywavesTranslator { tywaves: self, variable_cache: HashMap::new() };
+        inject_values(pdg, vcd_path, &self.extra_scopes, self.history_window, self.golden.as_ref(), &mut translator)
+    }
+}
+
+/// Injects simulation data straight from a VCD file, for designs without HGLDD debug information
+pub struct RawVcdInterface {
+    extra_scopes: Vec<String>,
+    history_window: Option<u32>,
+    golden: Option<GoldenValues>
+}
+
+impl RawVcdInterface {
+    pub fn new(extra_scopes: Vec<String>) -> Self {
+        Self { extra_scopes, history_window: None, golden: None }
+    }
+
+    /// Also attach the previous value of each node's signal and its values in the given number of cycles around the node.
+    pub fn with_value_history(mut self, window: u32) -> Self {
+        self.history_window = Some(window);
+        self
+    }
+
+    /// Also check the injected values against the values expected by a golden reference, and annotate mismatches.
+    pub fn with_golden_values(mut self, golden: GoldenValues) -> Self {
+        self.golden = Some(golden);
+        self
+    }
 
     pub fn inject_sim_data(&self, pdg: &mut ExportablePDG, vcd_path: impl AsRef<Path>) -> Result<()> {
-        let file = File::open(vcd_path)?;
-        let reader = BufReader::new(file);
-        let mut parser = vcd::Parser::new(reader);
-        let header = parser.parse_header()?;
+        inject_values(pdg, vcd_path, &self.extra_scopes, self.history_window, self.golden.as_ref(), &mut RawVcdTranslator)
+    }
+}
 
-        let signal_mapping = build_signal_map(&header);
+/// The source of the simulation values that are attached to the nodes of a DPDG
+pub enum SimDataInjector {
+    Tywaves(TywavesInterface),
+    RawVcd(RawVcdInterface)
+}
+
+impl SimDataInjector {
+    /// Uses Tywaves if an HGLDD directory is given and can be loaded. Otherwise, the values are taken from the VCD file
+    /// without type information.
+    pub fn new(hgldd_dir: Option<&Path>, extra_scopes: Vec<String>, top_module: &String) -> Self {
+        if let Some(hgldd_dir) = hgldd_dir {
+            match TywavesInterface::new(hgldd_dir, extra_scopes.clone(), top_module) {
+                Ok(tywaves) => return SimDataInjector::Tywaves(tywaves),
+                Err(e) => println!("Could not load the HGLDD files, falling back to untyped VCD values: {}", e)
+            }
+        }
+        SimDataInjector::RawVcd(RawVcdInterface::new(extra_scopes))
+    }
 
-        let mut node_map: HashMap<i64, Vec<&mut ExportablePDGNode>> = HashMap::new();
-        for node in &mut pdg.vertices {
-            node_map.entry(node.timestamp).or_default().push(node);
+    pub fn with_value_history(self, window: u32) -> Self {
+        match self {
+            SimDataInjector::Tywaves(tywaves) => SimDataInjector::Tywaves(tywaves.with_value_history(window)),
+            SimDataInjector::RawVcd(raw) => SimDataInjector::RawVcd(raw.with_value_history(window))
         }
+    }
 
-        let top_path: Vec<String> = vec!["TOP".into(), "svsimTestbench".into(), "dut".into()];
+    pub fn with_golden_values(self, golden: GoldenValues) -> Self {
+        match self {
+            SimDataInjector::Tywaves(tywaves) => SimDataInjector::Tywaves(tywaves.with_golden_values(golden)),
+            SimDataInjector::RawVcd(raw) => SimDataInjector::RawVcd(raw.with_golden_values(golden))
+        }
+    }
 
-        let clock = header.find_var(&["TOP", "svsimTestbench", "dut", "clock"]).ok_or(Error::ClockNotFoundError)?.code;
-        
-        // The rewritten VCD is a bit weird. It's best to squash all the changes (keep only the last one) for each timestep
-        // (needs hashmap). Then on the timestamp after a clock cycle, update the global hashmap and add the values to the nodes
-
-        let mut values_cache: HashMap<String, String> = HashMap::new();
-        let mut tywaves_variable_cache: HashMap<Vec<String>, Option<Variable>> = HashMap::new();
-        let mut rising_edge_found = false;
-        let mut current_timestamp: i64 = -1;
-        let mut clock_val = vcd::Value::V0;
-        let mut cycle_changes: HashMap<IdCode, vcd::Vector> = HashMap::new();
-        for command in parser {
-            let command = command?;
-            match command {
-                Command::Timestamp(t) => {
-                    // println!("Timestamp: {t}, current time: {current_timestamp}");
-                    // Update the global hashmap with the changes
-                    if rising_edge_found {
-                        if current_timestamp < 0 {
-                            current_timestamp = 0;
-                        }
-                        rising_edge_found = false;
-                        for (k,v) in &cycle_changes {
-                            let Some(signals) = signal_mapping.get(k) else {
-                                continue;
-                            };
-                            for signal in signals {
-                                values_cache.insert(signal.clone(), v.to_string());
-                            }
-                        }
-                        if let Some(nodes) = node_map.get_mut(&current_timestamp) {
-                            for node in nodes {
-                                if let Some(related_signal) = &node.related_signal {
-                                    let mut hier_path = top_path.clone();
-                                    hier_path.extend_from_slice(&related_signal.signal_path.split(".").map(|s| s.to_string()).collect::<Vec<_>>());
-
-                                    // avoids the hier_path clone() when using .entry()
-                                    let ty_var = if let Some(v) = tywaves_variable_cache.get(&hier_path) {
-                                        v
-                                    } else {
-                                        tywaves_variable_cache.insert(hier_path.clone(), self.find_signal(&hier_path).ok());
-                                        tywaves_variable_cache.get(&hier_path).unwrap()
-                                    };
-                                    // let ty_var = self.find_signal(&hier_path).ok();
-                                    // println!("{:#?}", ty_var);
-                                    if let (Some(value), Some(tywaves_signal)) = (values_cache.get(&related_signal.signal_path), ty_var)  {
-                                        let path_parts = related_signal.field_path.split(".").collect::<Vec<_>>();
-                                        node.sim_data =  self.translate_variable_field(&tywaves_signal, &value, &path_parts, None);
-                                    }
-                                }
-                            }
-                        }
-
-                        current_timestamp += 1;
-                        cycle_changes.clear();
-                    } else {
-                        // We need to determine the exact signal changes that occurred on the falling edge and put
-                        // println!("{current_timestamp}");
-                        // println!("{:#?}", cycle_changes);
-                        for (k,v) in &cycle_changes {
-                            let Some(signals) = signal_mapping.get(k) else {
-                                continue;
-                            };
-                            for signal in signals {
-                                values_cache.insert(signal.clone(), v.to_string());
-                            }
-                        }
-                        let time = if current_timestamp == -1 {
-                            current_timestamp
-                        } else {
-                            current_timestamp.saturating_sub(1)
-                        };
-                        if let Some(nodes) = node_map.get_mut(&time) {
-                            for node in nodes {
-                                if let Some(related_signal) = &node.related_signal {
-                                    let mut hier_path = top_path.clone();
-                                    hier_path.extend_from_slice(&related_signal.signal_path.split(".").map(|s| s.to_string()).collect::<Vec<_>>());
-
-                                    // avoids the hier_path clone() when using .entry()
-                                    let ty_var = if let Some(v) = tywaves_variable_cache.get(&hier_path) {
-                                        v
-                                    } else {
-                                        tywaves_variable_cache.insert(hier_path.clone(), self.find_signal(&hier_path).ok());
-                                        tywaves_variable_cache.get(&hier_path).unwrap()
-                                    };
-
-                                    // println!("{:#?}", ty_var);
-                                    if let (Some(value), Some(tywaves_signal)) = (values_cache.get(&related_signal.signal_path), ty_var)  {
-                                        let path_parts = related_signal.field_path.split(".").collect::<Vec<_>>();
-                                        node.sim_data =  self.translate_variable_field(&tywaves_signal, &value, &path_parts, None);
-                                    }
-                                }
-                            }
-                        }
-                        cycle_changes.clear();
-                    }
+    /// Attaches the simulation values to the nodes of a DPDG. For Tywaves, the VCD file is rewritten first.
+    pub fn inject_sim_data(&self, pdg: &mut ExportablePDG, vcd_path: &Path) -> Result<()> {
+        match self {
+            SimDataInjector::Tywaves(tywaves) => {
+                let tywaves_vcd_path = tywaves.vcd_rewrite(vcd_path)?;
+                println!("VCD rewritten");
+                tywaves.inject_sim_data(pdg, &tywaves_vcd_path)
+            }
+            SimDataInjector::RawVcd(raw) => raw.inject_sim_data(pdg, vcd_path)
+        }
+    }
+}
+
+/// Reads a value cached by `store_cached`. A missing or unreadable cache file (e.g. written by another version) is not
+/// an error, the value is computed again instead.
+fn load_cached<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let file = File::open(path).ok()?;
+    match bincode::deserialize_from(BufReader::new(file)) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            println!("Ignoring the unreadable cache file {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn store_cached<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    // Like rewritten VCDs, the value is written to a file unique to this process first and then moved in place
+    let partial_path = path.with_extension(format!("{}.partial", std::process::id()));
+    let writer = BufWriter::new(File::create(&partial_path)?);
+    if let Err(e) = bincode::serialize_into(writer, value) {
+        let _ = fs::remove_file(&partial_path);
+        return Err(e.into());
+    }
+    fs::rename(&partial_path, path)?;
+    Ok(())
+}
+
+/// Translates the raw values in a VCD file into typed simulation values
+trait ValueTranslator {
+    /// The name of the VCD variable, relative to the DUT, that holds the value of a signal
+    fn vcd_name(&self, signal: &PDGSpecRelatedSignal) -> String;
+
+    fn translate(&mut self, signal: &PDGSpecRelatedSignal, raw_val_vcd: &str, var: VcdVarInfo) -> Option<SimValue>;
+}
+
+/// What the VCD header tells about a variable
+#[derive(Clone, Copy)]
+struct VcdVarInfo {
+    width: u32,
+    /// Verilog `integer` variables are signed, all other VCD variables are plain bit vectors
+    signed: bool
+}
+
+impl VcdVarInfo {
+    /// The variable of a value that does not appear in the header
+    fn unknown(raw_val_vcd: &str) -> Self {
+        Self { width: raw_val_vcd.len() as u32, signed: false }
+    }
+}
+
+/// Translates values using the Tywaves type information. Aggregates are single variables in the rewritten VCD,
+/// so fields are selected by traversing the Tywaves variable.
+struct TywavesTranslator<'a> {
+    tywaves: &'a TywavesInterface,
+    variable_cache: HashMap<Vec<String>, Option<Variable>>
+}
+
+impl ValueTranslator for TywavesTranslator<'_> {
+    fn vcd_name(&self, signal: &PDGSpecRelatedSignal) -> String {
+        signal.signal_path.clone()
+    }
+
+    fn translate(&mut self, signal: &PDGSpecRelatedSignal, raw_val_vcd: &str, _var: VcdVarInfo) -> Option<SimValue> {
+        let mut hier_path = self.tywaves.extra_scopes.clone();
+        hier_path.extend(signal.signal_path.split(".").map(|s| s.to_string()));
+        let tywaves_signal = self.variable_cache.entry(hier_path)
+            .or_insert_with_key(|path| self.tywaves.find_signal(path).ok())
+            .as_ref()?;
+        let path_parts = signal.field_path.split(".").filter(|p| !p.is_empty()).collect::<Vec<_>>();
+        self.tywaves.translate_variable_field(tywaves_signal, raw_val_vcd, &path_parts, None)
+    }
+}
+
+/// Translates values without type information. Fields of aggregates are separate variables in the VCD, named after
+/// the lowered FIRRTL signal (e.g. `io_out_bits`). Only `integer` variables are known to be signed, so those are shown
+/// as SInt, single bits as Bool and everything else as UInt.
+struct RawVcdTranslator;
+
+impl ValueTranslator for RawVcdTranslator {
+    fn vcd_name(&self, signal: &PDGSpecRelatedSignal) -> String {
+        if signal.field_path.is_empty() {
+            signal.signal_path.clone()
+        } else {
+            format!("{}_{}", signal.signal_path, signal.field_path.replace(".", "_"))
+        }
+    }
+
+    fn translate(&mut self, _signal: &PDGSpecRelatedSignal, raw_val_vcd: &str, var: VcdVarInfo) -> Option<SimValue> {
+        // The PDG does not contain the types, and Chisel emits SInt signals as plain vectors. Those can still be shown as
+        // signed numbers with a format override.
+        let VcdVarInfo { width, signed } = var;
+        let type_name = match (signed, width) {
+            (true, _) => format!("SInt<{}>", width),
+            (false, 1) => "Bool".to_string(),
+            (false, _) => format!("UInt<{}>", width)
+        };
+        Some(SimValue {
+            type_name: Some(type_name),
+            width,
+            bits: pad_bits(raw_val_vcd, width as usize),
+            signed,
+            kind: SimValueKind::Ground,
+            enum_variant: None,
+            binary_point: None,
+            fields: vec![],
+            display: None
+        })
+    }
+}
+
+// To inject simulation data into the graph:
+// We essentially want to associate simulation values with nodes.
+// Due to the way the timestamps are set up, it should be possible to just grab the values from the 
+// timestamps of the nodes. We do need a cache for if the values don't change
+// 1) Read in a cycle of changes, update the cache
+// 2) For each node, look up the signal in the VCD, then translate the value (for Tywaves, this selects the field
+// using the field path)
+// 3) Add the information to the node
+
+fn inject_values(
+    pdg: &mut ExportablePDG,
+    vcd_path: impl AsRef<Path>,
+    extra_scopes: &[String],
+    history_window: Option<u32>,
+    golden: Option<&GoldenValues>,
+    translator: &mut impl ValueTranslator
+) -> Result<()> {
+    let file = File::open(vcd_path)?;
+    let reader = BufReader::new(file);
+    let mut parser = vcd::Parser::new(reader);
+    let header = parser.parse_header()?;
+
+    let (signal_mapping, signal_vars) = build_signal_map(&header, extra_scopes)?;
+
+    let mut node_map: HashMap<i64, Vec<&mut ExportablePDGNode>> = HashMap::new();
+    for node in &mut pdg.vertices {
+        node_map.entry(node.timestamp).or_default().push(node);
+    }
+
+    let mut clock_path = extra_scopes.to_vec();
+    clock_path.push("clock".into());
+    let clock = header.find_var(&clock_path).ok_or(Error::ClockNotFoundError)?.code;
+    
+    // The rewritten VCD is a bit weird. It's best to squash all the changes (keep only the last one) for each timestep
+    // (needs hashmap). Then on the timestamp after a clock cycle, update the global hashmap and add the values to the nodes
+
+    let mut values_cache: HashMap<String, String> = HashMap::new();
+    let mut rising_edge_found = false;
+    // The number of rising edges so far, which is the time slot of the current values
+    let mut current_timestamp: i64 = 0;
+    let mut clock_val = vcd::Value::V0;
+    let mut cycle_changes: HashMap<IdCode, String> = HashMap::new();
+    let mut timeline = ValueTimeline::default();
+    if history_window.is_some() {
+        for node in node_map.values().flatten() {
+            if let Some(related_signal) = &node.related_signal {
+                timeline.track(&translator.vcd_name(related_signal));
+            }
+        }
+    }
+    for command in parser {
+        let command = command?;
+        match command {
+            Command::Timestamp(_t) => {
+                // The changes at a rising edge are the first values of the next time slot
+                if rising_edge_found {
+                    rising_edge_found = false;
+                    current_timestamp += 1;
                 }
-                Command::ChangeVector(i, v) if i == clock => {
-                    let new_clock_val  = v.get(0).unwrap();
-                    if clock_val == vcd::Value::V0 && new_clock_val == vcd::Value::V1 {
-                        // println!("Rising edge");
-                        rising_edge_found = true;
+
+                // Update the global hashmap with the changes
+                for (k,v) in &cycle_changes {
+                    let Some(signals) = signal_mapping.get(k) else {
+                        continue;
+                    };
+                    for signal in signals {
+                        timeline.record(current_timestamp, signal, v);
+                        values_cache.insert(signal.clone(), v.clone());
                     }
-                    clock_val = new_clock_val;
                 }
-                Command::ChangeVector(i, v) => {
-                    // println!("Change in {:?}: {v}", i);
-                    cycle_changes.insert(i, v);
-                    // if let Some(probes) = self.probes.get(&i) {
-                    //     for probe in probes {
-                    //         self.probe_change_buffer.push((probe.clone(), bitvector_to_unsigned(&v)));
-                    //     }
-                    // }
+                if let Some(nodes) = node_map.get_mut(&current_timestamp) {
+                    assign_values(nodes, &values_cache, &signal_vars, translator);
+                }
+                // The initial state has the values from before the first rising edge
+                if current_timestamp == 0 && let Some(nodes) = node_map.get_mut(&INITIAL_STATE_TIMESTAMP) {
+                    assign_values(nodes, &values_cache, &signal_vars, translator);
                 }
-                // Everything is vectorized by the VCD rewriter, so no scalar changes.
-                _ => ()
+                cycle_changes.clear();
+            }
+            Command::ChangeVector(i, v) if i == clock => {
+                let new_clock_val  = v.get(0).unwrap();
+                if clock_val == vcd::Value::V0 && new_clock_val == vcd::Value::V1 {
+                    rising_edge_found = true;
+                }
+                clock_val = new_clock_val;
+            }
+            Command::ChangeScalar(i, v) if i == clock => {
+                if clock_val == vcd::Value::V0 && v == vcd::Value::V1 {
+                    rising_edge_found = true;
+                }
+                clock_val = v;
+            }
+            Command::ChangeVector(i, v) => {
+                cycle_changes.insert(i, v.to_string());
             }
+            // Everything is vectorized by the VCD rewriter, but VCDs that were not rewritten also have scalar changes
+            Command::ChangeScalar(i, v) => {
+                cycle_changes.insert(i, v.to_string());
+            }
+            _ => ()
         }
+    }
+
+    if let Some(window) = history_window {
+        attach_value_history(pdg, &timeline, &signal_vars, translator, window as i64, current_timestamp);
+    }
+
+    // The timestamps are still the cycles of the simulation here, which the golden values are given in
+    if let Some(golden) = golden {
+        let mismatches = annotate_mismatches(pdg, golden);
+        println!("{} values differ from the golden reference", mismatches);
+    }
+
+    Ok(())
+}
 
-        Ok(())
+/// Sets the simulation values of nodes to the current values of their related signals
+fn assign_values(
+    nodes: &mut [&mut ExportablePDGNode],
+    values_cache: &HashMap<String, String>,
+    signal_vars: &HashMap<String, VcdVarInfo>,
+    translator: &mut impl ValueTranslator
+) {
+    for node in nodes {
+        let Some(related_signal) = &node.related_signal else {
+            continue;
+        };
+        let name = translator.vcd_name(related_signal);
+        if let Some(value) = values_cache.get(&name) {
+            let var = signal_vars.get(&name).copied().unwrap_or_else(|| VcdVarInfo::unknown(value));
+            node.sim_data = translator.translate(related_signal, value, var);
+        }
     }
 }
 
-/// Build a map of IdCode -> Hierarchical signal name
-fn build_signal_map(header: &vcd::Header) -> HashMap<IdCode, Vec<String>> {
+/// Attaches the values of the related signal of each node in the cycles around the node, as well as its previous value.
+fn attach_value_history(
+    pdg: &mut ExportablePDG,
+    timeline: &ValueTimeline,
+    signal_vars: &HashMap<String, VcdVarInfo>,
+    translator: &mut impl ValueTranslator,
+    window: i64,
+    last_timestamp: i64
+) {
+    for node in &mut pdg.vertices {
+        let Some(related_signal) = &node.related_signal else {
+            continue;
+        };
+        let name = translator.vcd_name(related_signal);
+        let mut value_at = |timestamp: i64| {
+            // The initial state has the values from before the first rising edge, like time slot 0
+            let raw = timeline.value_at(&name, timestamp.max(0))?;
+            let var = signal_vars.get(&name).copied().unwrap_or_else(|| VcdVarInfo::unknown(raw));
+            translator.translate(related_signal, raw, var)
+        };
+
+        let previous = if node.timestamp > INITIAL_STATE_TIMESTAMP { value_at(node.timestamp - 1) } else { None };
+        let changed = previous.as_ref().map(|v| &v.bits) != node.sim_data.as_ref().map(|v| &v.bits);
+        let first = (node.timestamp - window).max(INITIAL_STATE_TIMESTAMP);
+        let last = (node.timestamp + window).min(last_timestamp);
+        let window = (first..=last).filter_map(|t| Some((t, value_at(t)?))).collect();
+        node.sim_history = Some(SimHistory { previous, changed, window });
+    }
+}
+
+/// The values of a set of signals over time. A value is only stored when it changes.
+#[derive(Default)]
+struct ValueTimeline {
+    changes: HashMap<String, Vec<(i64, String)>>
+}
+
+impl ValueTimeline {
+    fn track(&mut self, signal: &str) {
+        if !self.changes.contains_key(signal) {
+            self.changes.insert(signal.to_string(), vec![]);
+        }
+    }
+
+    /// Records the value of a signal at a timestamp, if the signal is tracked
+    fn record(&mut self, timestamp: i64, signal: &str, value: &str) {
+        let Some(changes) = self.changes.get_mut(signal) else {
+            return;
+        };
+        match changes.last_mut() {
+            // A later change in the same cycle replaces the earlier one
+            Some((t, v)) if *t == timestamp => *v = value.to_string(),
+            Some((_, v)) if v == value => (),
+            _ => changes.push((timestamp, value.to_string()))
+        }
+    }
+
+    fn value_at(&self, signal: &str, timestamp: i64) -> Option<&str> {
+        let changes = self.changes.get(signal)?;
+        let idx = changes.partition_point(|(t, _)| *t <= timestamp).checked_sub(1)?;
+        Some(&changes[idx].1)
+    }
+}
+
+/// The number of fractional bits of a fixed-point or interval type. It is taken from the constructor parameters or,
+/// if those are not available, from the type name (e.g. `FixedPoint<8><<4>>` or `Interval[0,10].4BP`).
+fn binary_point(info: &TypeInfo) -> Option<u32> {
+    let tpe = &info.type_name;
+    if !tpe.starts_with("FixedPoint") && !tpe.starts_with("Interval") {
+        return None;
+    }
+    let from_params = info.params.iter()
+        .find(|p| p.name.to_lowercase().contains("binarypoint"))
+        .and_then(|p| p.value.as_ref()?.trim_end_matches(".BP").parse().ok());
+    from_params.or_else(|| {
+        let (_, rest) = tpe.split_once("<<").or_else(|| tpe.rsplit_once('.'))?;
+        rest.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().ok()
+    })
+}
+
+/// Extends a raw VCD value to the given width. Like in VCD files, values are extended with zeros,
+/// unless the most significant bit is undefined.
+fn pad_bits(raw_val_vcd: &str, width: usize) -> String {
+    let Some(padding) = width.checked_sub(raw_val_vcd.len()).filter(|p| *p > 0) else {
+        return raw_val_vcd.to_string();
+    };
+    let fill = raw_val_vcd.chars().next().filter(|c| matches!(c, 'x' | 'X' | 'z' | 'Z')).unwrap_or('0');
+    std::iter::repeat_n(fill, padding).chain(raw_val_vcd.chars()).collect()
+}
+
+/// The hierarchical signal names of each IdCode, relative to the scope of the DUT
+type SignalMap = HashMap<IdCode, Vec<String>>;
+
+/// Build a map of IdCode -> Hierarchical signal name, relative to the scope of the DUT, and a map of the signal variables
+fn build_signal_map(header: &vcd::Header, dut_scope: &[String]) -> Result<(SignalMap, HashMap<String, VcdVarInfo>)> {
     let mut signals = HashMap::new();
-    if let Some(dut) = header.find_scope(&["TOP", "svsimTestbench", "dut"]) {
-        let mut stack = vec![];
-        stack.extend_from_slice(&dut.items.iter().map(|i| ("".to_string(), i)).collect::<Vec<_>>());
-        while let Some((prefix, item)) = stack.pop() {
-            match item {
-                vcd::ScopeItem::Scope(scope) => {
-                    stack.extend_from_slice(&scope.items.iter().map(|i| (prefix.to_string() + &scope.identifier, i)).collect::<Vec<_>>());
-                }
-                vcd::ScopeItem::Var(var) => {
-                    // Probes may have the same IdCode if they are driven by the same value.
-                    // We need to check if it exists and update the vector if it does.
-                    let name = if prefix.is_empty() { var.reference.clone() } else { prefix.clone() + "." + &var.reference };
-                    signals.entry(var.code).and_modify(|e: &mut Vec<String>| e.push(name.clone())).or_insert(vec![name]);
-                }
-                _ => ()
+    let mut vars = HashMap::new();
+    let dut_items = if dut_scope.is_empty() {
+        &header.items
+    } else {
+        &header.find_scope(dut_scope).ok_or(Error::ScopeNotFound(dut_scope.join(".")))?.items
+    };
+    let mut stack = vec![];
+    stack.extend_from_slice(&dut_items.iter().map(|i| ("".to_string(), i)).collect::<Vec<_>>());
+    while let Some((prefix, item)) = stack.pop() {
+        match item {
+            vcd::ScopeItem::Scope(scope) => {
+                let new_prefix = if prefix.is_empty() {
+                    scope.identifier.clone()
+                } else {
+                    prefix.to_string() + "." + &scope.identifier
+                };
+                stack.extend_from_slice(&scope.items.iter().map(|i| (new_prefix.clone(), i)).collect::<Vec<_>>());
             }
+            vcd::ScopeItem::Var(var) => {
+                // Probes may have the same IdCode if they are driven by the same value.
+                // We need to check if it exists and update the vector if it does.
+                let name = if prefix.is_empty() { var.reference.clone() } else { prefix.clone() + "." + &var.reference };
+                vars.insert(name.clone(), VcdVarInfo { width: var.size, signed: var.var_type == vcd::VarType::Integer });
+                signals.entry(var.code).and_modify(|e: &mut Vec<String>| e.push(name.clone())).or_insert(vec![name]);
+            }
+            _ => ()
         }
     }
 
-    signals
-}
\ No newline at end of file
+    Ok((signals, vars))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate_raw(raw_val_vcd: &str, width: u32, signed: bool) -> SimValue {
+        let signal = PDGSpecRelatedSignal { signal_path: "io".into(), field_path: "out".into() };
+        RawVcdTranslator.translate(&signal, raw_val_vcd, VcdVarInfo { width, signed }).unwrap()
+    }
+
+    #[test]
+    fn raw_values_are_typed_by_their_vcd_variable() {
+        let bit = translate_raw("1", 1, false);
+        assert_eq!(bit.type_name.as_deref(), Some("Bool"));
+
+        let vector = translate_raw("101", 8, false);
+        assert_eq!(vector.type_name.as_deref(), Some("UInt<8>"));
+        assert_eq!(vector.bits, "00000101");
+        assert!(!vector.signed);
+
+        let integer = translate_raw("11111110", 32, true);
+        assert_eq!(integer.type_name.as_deref(), Some("SInt<32>"));
+        assert_eq!(integer.width, 32);
+        assert!(integer.signed);
+    }
+
+    #[test]
+    fn cached_values_round_trip() {
+        let path = std::env::temp_dir().join(format!("chiseltrace-test-{}.cache", std::process::id()));
+        let value = vec![("top".to_string(), vec![1u32, 2, 3]), ("dut".to_string(), vec![])];
+        store_cached(&path, &value).unwrap();
+        assert_eq!(load_cached::<Vec<(String, Vec<u32>)>>(&path), Some(value));
+
+        // A cache file that cannot be decoded is ignored
+        fs::write(&path, [0xff; 3]).unwrap();
+        assert_eq!(load_cached::<Vec<(String, Vec<u32>)>>(&path), None);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(load_cached::<Vec<(String, Vec<u32>)>>(&path), None);
+    }
+}
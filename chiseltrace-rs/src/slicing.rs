@@ -1,10 +1,12 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use std::path::Path;
 use std::{cell::RefCell, collections::HashMap, fs::File, io::BufWriter, rc::Rc};
 use anyhow::{anyhow, Result};
+use itertools::Itertools;
 use crate::errors::Error;
 use crate::graphbuilder::DynPDGNode;
-use crate::pdg_spec::{CFGSpecStatement, ExportablePDG, ExportableSlice, LinkedPDGNode, PDGSpec, PDGSpecEdge};
+use crate::pdg_spec::{CFGSpecStatement, ExportablePDG, ExportableSlice, ExportableSliceStatement, LinkedPDGNode, PDGSpec, PDGSpecEdge, PDGSpecNode};
+use crate::sarif::SarifLog;
 
 /// Function that takes in a PDG in Spec form (i.e. separate vertices and edge lists, linked by indices)
 /// and produces a list of vertices that refer to their dependence nodes.
@@ -166,8 +168,18 @@ pub fn write_static_slice<P: AsRef<Path>>(pdg: &ExportablePDG, path: P) -> Resul
     Ok(())
 }
 
-pub fn write_dynamic_slice<P: AsRef<Path>>(pdg: &Rc<RefCell<DynPDGNode>>, path: P) -> Result<()> {
-    let mut unique_statements = HashSet::new();
+pub fn write_static_slice_sarif<P: AsRef<Path>>(pdg: &ExportablePDG, path: P) -> Result<()> {
+    let output_file = File::create(path)?;
+    let writer = BufWriter::new(output_file);
+
+    let statements = pdg.vertices.iter().map(|x| x.clone().into()).unique().collect::<Vec<ExportableSliceStatement>>();
+    serde_json::to_writer_pretty(writer, &SarifLog::from_static_slice(&statements))?;
+    Ok(())
+}
+
+/// Collects the unique statements in a dynamic slice, together with the timestamps at which each statement was active.
+fn collect_dynamic_slice(pdg: &Rc<RefCell<DynPDGNode>>) -> HashMap<Rc<PDGSpecNode>, BTreeSet<i64>> {
+    let mut unique_statements: HashMap<Rc<PDGSpecNode>, BTreeSet<i64>> = HashMap::new();
     let mut stack = vec![pdg.clone()];
     let mut scanned_nodes = vec![];
     while let Some(node) = stack.pop() {
@@ -180,12 +192,53 @@ pub fn write_dynamic_slice<P: AsRef<Path>>(pdg: &Rc<RefCell<DynPDGNode>>, path:
                 }
         }));
         scanned_nodes.push(node.clone());
-        unique_statements.insert(node.borrow().inner.clone());
+        unique_statements.entry(node.borrow().inner.clone()).or_default().insert(node.borrow().timestamp);
+    }
+
+    unique_statements
+}
+
+pub fn write_dynamic_slice<P: AsRef<Path>>(pdg: &Rc<RefCell<DynPDGNode>>, path: P) -> Result<()> {
+    let unique_statements = collect_dynamic_slice(pdg);
+
+    let output_file = File::create(path)?;
+    let writer = BufWriter::new(output_file);
+    serde_json::to_writer_pretty(writer, &ExportableSlice { statements: unique_statements.into_keys().map(|x| x.into()).collect::<Vec<_>>() })?;
+
+    Ok(())
+}
+
+pub fn write_dynamic_slice_sarif<P: AsRef<Path>>(pdg: &Rc<RefCell<DynPDGNode>>, path: P) -> Result<()> {
+    // Different FIRRTL statements may map to the same source location. Their cycles are merged into one result.
+    let mut statements: HashMap<ExportableSliceStatement, BTreeSet<i64>> = HashMap::new();
+    for (stmt, cycles) in collect_dynamic_slice(pdg) {
+        statements.entry(stmt.into()).or_default().extend(cycles);
+    }
+    let statements = statements.into_iter()
+        .sorted_by(|(a, _), (b, _)| (&a.file, a.line, a.char).cmp(&(&b.file, b.line, b.char)))
+        .collect::<Vec<_>>();
+
+    let output_file = File::create(path)?;
+    let writer = BufWriter::new(output_file);
+    serde_json::to_writer_pretty(writer, &SarifLog::from_dynamic_slice(&statements))?;
+
+    Ok(())
+}
+
+/// Writes the statements of an exported DPDG as SARIF results, with the timestamps at which each statement was active.
+pub fn write_dpdg_sarif<P: AsRef<Path>>(pdg: &ExportablePDG, path: P) -> Result<()> {
+    let mut statements: HashMap<ExportableSliceStatement, BTreeSet<i64>> = HashMap::new();
+    // Nodes without a source location, such as grouped modules, are left out
+    for v in pdg.vertices.iter().filter(|v| !v.file.is_empty()) {
+        statements.entry(v.clone().into()).or_default().insert(v.timestamp);
     }
+    let statements = statements.into_iter()
+        .sorted_by(|(a, _), (b, _)| (&a.file, a.line, a.char).cmp(&(&b.file, b.line, b.char)))
+        .collect::<Vec<_>>();
 
     let output_file = File::create(path)?;
     let writer = BufWriter::new(output_file);
-    serde_json::to_writer_pretty(writer, &ExportableSlice { statements: unique_statements.into_iter().map(|x| x.into()).collect::<Vec<_>>() })?;
+    serde_json::to_writer_pretty(writer, &SarifLog::from_dynamic_slice(&statements))?;
 
     Ok(())
 }
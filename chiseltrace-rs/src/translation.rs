@@ -0,0 +1,191 @@
+use crate::{pdg_spec::{ExportablePDGNode, SimValue, SimValueKind}, value_format::{signal_key, FormatOverrides, ValueFormat}};
+
+#[derive(Debug, Clone)]
+pub struct TranslationResult {
+    pub tpe: Option<String>,
+    pub value: String,
+    /// The translated fields of a bundle or the elements of a vec
+    pub fields: Vec<(String, TranslationResult)>
+}
+
+impl TranslationResult {
+    /// A label for the value that shows aggregates as an indented tree, one field per line.
+    pub fn label(&self) -> String {
+        let tpe = self.tpe.clone().unwrap_or_default();
+        if self.fields.is_empty() {
+            format!("{} {}", tpe, self.value)
+        } else {
+            let mut lines = vec![tpe];
+            self.push_tree_lines(&mut lines, 1);
+            lines.join("\n")
+        }
+    }
+
+    fn push_tree_lines(&self, lines: &mut Vec<String>, depth: usize) {
+        for (name, field) in &self.fields {
+            let indent = "  ".repeat(depth);
+            if field.fields.is_empty() {
+                lines.push(format!("{}{}: {}", indent, name, field.value));
+            } else {
+                lines.push(format!("{}{}:", indent, name));
+                field.push_tree_lines(lines, depth + 1);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TranslationStrategy {
+    /// Tries to detect the type based on the source language type
+    Auto,
+    /// Interprets everything as a UInt
+    UInt,
+    /// Does not perform any translation
+    None,
+    /// Uses a format chosen by the user
+    Format(ValueFormat)
+}
+
+/// Translates the simulation value of a node. The format overrides of the user for the signal, its fields and their types
+/// take precedence over the automatic translation.
+pub fn interpret_node_value(node: &ExportablePDGNode, formats: &FormatOverrides) -> Option<TranslationResult> {
+    let val = node.sim_data.as_ref()?;
+    let key = node.related_signal.as_ref().map(signal_key);
+    Some(interpret_tywaves_value(val, key.as_deref(), formats))
+}
+
+/// The label of a node in the viewer. Control flow nodes show their predicate and the value it evaluated to.
+pub fn node_label(node: &ExportablePDGNode, formats: &FormatOverrides) -> String {
+    let Some(predicate) = &node.predicate else {
+        return node.name.clone();
+    };
+    if let Some(translated) = interpret_node_value(node, formats) {
+        format!("{} = {}", predicate, translated.value)
+    } else {
+        predicate.clone()
+    }
+}
+
+/// Translates a simulation value of the signal or field with the given name
+pub fn interpret_tywaves_value(val: &SimValue, key: Option<&str>, formats: &FormatOverrides) -> TranslationResult {
+    let stategy = formats.lookup(key, val.type_name.as_deref())
+        .map_or(TranslationStrategy::Auto, TranslationStrategy::Format);
+    let fields = val.fields.iter()
+        .map(|(name, field)| {
+            let field_key = key.map(|k| format!("{}.{}", k, name));
+            (name.clone(), interpret_tywaves_value(field, field_key.as_deref(), formats))
+        })
+        .collect::<Vec<_>>();
+
+    let value = match val.kind {
+        SimValueKind::Bundle => {
+            let fields = fields.iter().map(|(name, f)| format!("{}: {}", name, f.value)).collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(", "))
+        }
+        SimValueKind::Vec => {
+            let elements = fields.iter().map(|(_, f)| f.value.clone()).collect::<Vec<_>>();
+            format!("[{}]", elements.join(", "))
+        }
+        SimValueKind::Ground => {
+            let value_part = val.bits.clone();
+            match stategy {
+                TranslationStrategy::Auto => auto_translate(value_part, val),
+                TranslationStrategy::UInt => translate_as_uint(value_part),
+                TranslationStrategy::None => value_part,
+                TranslationStrategy::Format(format) => format.format_bits(&value_part)
+            }
+        }
+    };
+
+    TranslationResult { tpe: val.type_name.clone(), value, fields }
+}
+
+fn auto_translate(bitstring: String, val: &SimValue) -> String {
+    if let Some(variant) = &val.enum_variant {
+        variant.clone()
+    } else if let Some(binary_point) = val.binary_point {
+        translate_as_fixed(bitstring, binary_point, val.signed)
+    } else if val.signed {
+        translate_as_sint(bitstring)
+    } else if let Some(tpe) = &val.type_name {
+        if tpe.contains("UInt") {
+            translate_as_uint(bitstring)
+        } else if tpe.contains("SInt") {
+            translate_as_sint(bitstring)
+        } else if tpe.contains("Bool") {
+            translate_as_bool(bitstring)
+        } else if tpe.contains("logic") && val.width <= 1 {
+            translate_as_bool(bitstring)
+        } else if tpe.contains("logic") {
+            translate_as_uint(bitstring)
+        } else {
+            bitstring
+        }
+    } else {
+        bitstring
+    }
+}
+
+/// Interprets the bits as a fixed-point number with the given number of fractional bits
+fn translate_as_fixed(bitstring: String, binary_point: u32, signed: bool) -> String {
+    let integer = if signed { translate_as_sint(bitstring) } else { translate_as_uint(bitstring) };
+    let Ok(integer) = integer.parse::<i128>() else {
+        return integer;
+    };
+    (integer as f64 / 2f64.powi(binary_point as i32)).to_string()
+}
+
+fn translate_as_uint(bitstring: String) -> String {
+    let mut val: u128 = 0;
+    let mut bitval = 1;
+
+    for ch in bitstring.chars().rev() {
+        match ch {
+            '1' => val += bitval,
+            '0' => (),
+            _ => return "UDF".into() // Undefined for inputs such as xx
+        }
+        bitval <<= 1;
+    }
+
+    val.to_string()
+}
+
+fn translate_as_sint(bitstring: String) -> String {
+    if bitstring.is_empty() {
+        return "UDF".into();
+    }
+
+    let mut val: i128 = 0;
+    let chars: Vec<char> = bitstring.chars().collect();
+    let msb_index = chars.len() - 1;
+
+    // Process all bits except MSB first (LSB to MSB-1)
+    for (i, &ch) in chars.iter().rev().enumerate() {
+        if i == msb_index {
+            continue;
+        }
+        match ch {
+            '1' => val += 1 << i,
+            '0' => (),
+            _ => return "UDF".into(),
+        }
+    }
+
+    // Handle MSB (sign bit)
+    match chars[0] {
+        '1' => val -= 1 << msb_index,
+        '0' => (),
+        _ => return "UDF".into(),
+    }
+
+    val.to_string()
+}
+
+fn translate_as_bool(bitstring: String) -> String {
+    match bitstring.as_str() {
+        "1" => "true".into(),
+        "0" => "false".into(),
+        _ => "UDF".into()  // Undefined for any other input
+    }
+}
\ No newline at end of file
@@ -1,3 +1,7 @@
+use std::{collections::HashMap, fs::{self, read_to_string, File}, io, path::{Path, PathBuf}};
+
+use sha2::{Digest, Sha256};
+
 use crate::graphbuilder::CriterionType;
 
 pub fn parse_criterion(s: &str) -> Result<CriterionType, String> {
@@ -8,4 +12,56 @@ pub fn parse_criterion(s: &str) -> Result<CriterionType, String> {
         "signal" => Ok(CriterionType::Signal(value.into())),
         _ => Err(format!("Unknown criterion type '{}'", kind)),
     }
-}
\ No newline at end of file
+}
+
+/// Reads the given source files into memory, split into lines. Files that cannot be read are left out.
+pub fn read_source_files<'a>(paths: impl IntoIterator<Item = &'a str>) -> HashMap<String, Vec<String>> {
+    let mut source_files = HashMap::new();
+    for p in paths {
+        if source_files.contains_key(p) {
+            continue;
+        }
+        // This is a hacky fix that appends the root symbol / if the file is in the home directory.
+        // For some reason, the exported PDG does not contain this symbol.
+        // This will not work on windows.
+        let read_path = if p.starts_with("home") {
+            "/".to_string() + p
+        } else {
+            p.to_string()
+        };
+
+        if let Ok(contents) = read_to_string(&read_path) {
+            source_files.insert(p.to_string(), contents.lines().map(String::from).collect());
+        }
+    }
+    source_files
+}
+
+
+/// The per-user directory in which ChiselTrace caches derived files, such as rewritten VCDs. It is created if it does not exist.
+pub fn cache_dir() -> io::Result<PathBuf> {
+    let dir = dirs::cache_dir().unwrap_or_else(std::env::temp_dir).join("chiseltrace");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Feeds the contents of a file into a hasher. Directories are hashed recursively, including the names of their entries,
+/// in a deterministic order.
+pub fn hash_path(hasher: &mut Sha256, path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?.map(|e| e.map(|e| e.path())).collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            hasher.update(entry.file_name().unwrap_or_default().as_encoded_bytes());
+            hash_path(hasher, &entry)?;
+        }
+    } else {
+        io::copy(&mut File::open(path)?, hasher)?;
+    }
+    Ok(())
+}
+
+/// Formats a finished hash as a hexadecimal string, for use in file names.
+pub fn hex_digest(hasher: Sha256) -> String {
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
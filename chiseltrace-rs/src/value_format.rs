@@ -0,0 +1,242 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pdg_spec::{ExportablePDG, PDGSpecRelatedSignal, SimValue};
+
+/// A display format for simulation values that overrides the one derived from the type of the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueFormat {
+    Hex,
+    Bin,
+    Dec,
+    Signed,
+    Ascii
+}
+
+impl FromStr for ValueFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(ValueFormat::Hex),
+            "bin" => Ok(ValueFormat::Bin),
+            "dec" => Ok(ValueFormat::Dec),
+            "signed" => Ok(ValueFormat::Signed),
+            "ascii" => Ok(ValueFormat::Ascii),
+            _ => Err(format!("Unknown value format '{}', expected hex, bin, dec, signed or ascii", s))
+        }
+    }
+}
+
+impl Display for ValueFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ValueFormat::Hex => "hex",
+            ValueFormat::Bin => "bin",
+            ValueFormat::Dec => "dec",
+            ValueFormat::Signed => "signed",
+            ValueFormat::Ascii => "ascii"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ValueFormat {
+    /// Formats raw bits, most significant bit first. Values with undefined bits are shown as `UDF`,
+    /// except in the binary and hexadecimal formats, which can show them.
+    pub fn format_bits(&self, bits: &str) -> String {
+        match self {
+            ValueFormat::Hex => format!("0x{}", bits_to_hex(bits)),
+            ValueFormat::Bin => format!("0b{}", bits),
+            ValueFormat::Dec => bits_to_unsigned(bits).map_or("UDF".into(), |v| v.to_string()),
+            ValueFormat::Signed => bits_to_signed(bits).map_or("UDF".into(), |v| v.to_string()),
+            ValueFormat::Ascii => bits_to_ascii(bits).map_or("UDF".into(), |s| format!("{:?}", s))
+        }
+    }
+}
+
+/// User-chosen display formats for simulation values, per signal and per source language type.
+/// An override for a signal takes precedence over an override for its type.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FormatOverrides {
+    /// Keyed by the signal path, followed by the field path if the signal is an aggregate (e.g. `io.out.bits`)
+    pub signals: HashMap<String, ValueFormat>,
+    /// Keyed by the type name, with or without its parameters (e.g. `UInt<32>` or `UInt`)
+    pub types: HashMap<String, ValueFormat>
+}
+
+impl FormatOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.signals.is_empty() && self.types.is_empty()
+    }
+
+    /// Finds the format override for a signal and/or its type
+    pub fn lookup(&self, signal: Option<&str>, type_name: Option<&str>) -> Option<ValueFormat> {
+        let from_signal = signal.and_then(|s| self.signals.get(s));
+        let from_type = type_name.and_then(|t| self.types.get(t).or_else(|| self.types.get(base_type_name(t))));
+        from_signal.or(from_type).copied()
+    }
+
+    /// Formats a value of a signal if it, or one of its fields, has an override. The result is stored in the
+    /// `display` field of the (sub)value, so consumers of exported graphs do not have to format it themselves.
+    pub fn apply(&self, pdg: &mut ExportablePDG) {
+        if self.is_empty() {
+            return;
+        }
+        for vert in &mut pdg.vertices {
+            let Some(value) = &mut vert.sim_data else {
+                continue;
+            };
+            let key = vert.related_signal.as_ref().map(signal_key);
+            self.apply_to_value(value, key);
+        }
+    }
+
+    fn apply_to_value(&self, value: &mut SimValue, key: Option<String>) {
+        if value.fields.is_empty() {
+            value.display = self.lookup(key.as_deref(), value.type_name.as_deref()).map(|f| f.format_bits(&value.bits));
+        }
+        for (name, field) in &mut value.fields {
+            let field_key = key.as_ref().map(|k| format!("{}.{}", k, name));
+            self.apply_to_value(field, field_key);
+        }
+    }
+}
+
+/// The name by which a signal is referred to in the format overrides
+pub fn signal_key(signal: &PDGSpecRelatedSignal) -> String {
+    if signal.field_path.is_empty() {
+        signal.signal_path.clone()
+    } else {
+        format!("{}.{}", signal.signal_path, signal.field_path)
+    }
+}
+
+/// Parses a `name=format` pair, as used for format overrides on the command line
+pub fn parse_format_override(s: &str) -> Result<(String, ValueFormat), String> {
+    let (name, format) = s.rsplit_once('=')
+        .ok_or("Expected 'name=format' format")?;
+    Ok((name.to_string(), format.parse()?))
+}
+
+/// The type name without its parameters, e.g. `UInt` for `UInt<8>`
+fn base_type_name(type_name: &str) -> &str {
+    let end = type_name.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(type_name.len());
+    &type_name[..end]
+}
+
+fn bits_to_unsigned(bits: &str) -> Option<u128> {
+    let bits = bits.trim_start_matches('0');
+    if bits.len() > 128 {
+        return None;
+    }
+    bits.chars().try_fold(0u128, |acc, c| match c {
+        '0' => Some(acc << 1),
+        '1' => Some((acc << 1) | 1),
+        _ => None
+    })
+}
+
+fn bits_to_signed(bits: &str) -> Option<i128> {
+    if bits.is_empty() || bits.len() > 128 {
+        return None;
+    }
+    let magnitude = bits_to_unsigned(&bits[1..])? as i128;
+    match bits.chars().next()? {
+        '0' => Some(magnitude),
+        '1' => Some(magnitude + (-1i128 << (bits.len() - 1))),
+        _ => None
+    }
+}
+
+fn bits_to_hex(bits: &str) -> String {
+    // Align to whole nibbles from the least significant bit
+    let padding = (4 - bits.len() % 4) % 4;
+    let padded = "0".repeat(padding) + bits;
+    padded.as_bytes().chunks(4).map(|nibble| {
+        let nibble = std::str::from_utf8(nibble).unwrap_or_default();
+        match bits_to_unsigned(nibble) {
+            Some(v) => char::from_digit(v as u32, 16).unwrap_or('?'),
+            None if nibble.contains(['z', 'Z']) => 'z',
+            None => 'x'
+        }
+    }).collect()
+}
+
+fn bits_to_ascii(bits: &str) -> Option<String> {
+    let padding = (8 - bits.len() % 8) % 8;
+    let padded = "0".repeat(padding) + bits;
+    padded.as_bytes().chunks(8).map(|byte| {
+        let byte = bits_to_unsigned(std::str::from_utf8(byte).ok()?)? as u8;
+        Some(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_is_aligned_to_the_least_significant_bit() {
+        assert_eq!(bits_to_hex("101"), "5");
+        assert_eq!(bits_to_hex("100000000"), "100");
+        assert_eq!(bits_to_hex("11111111"), "ff");
+        assert_eq!(ValueFormat::Hex.format_bits("00101010"), "0x2a");
+    }
+
+    #[test]
+    fn hex_keeps_undefined_nibbles() {
+        assert_eq!(bits_to_hex("0001xxxx"), "1x");
+        assert_eq!(bits_to_hex("zzzz0001"), "z1");
+        // A nibble with both undefined and high-impedance bits counts as high impedance
+        assert_eq!(bits_to_hex("x0z1"), "z");
+    }
+
+    #[test]
+    fn signed_values_are_twos_complement() {
+        assert_eq!(ValueFormat::Signed.format_bits("1110"), "-2");
+        assert_eq!(ValueFormat::Signed.format_bits("0110"), "6");
+        assert_eq!(ValueFormat::Signed.format_bits("1"), "-1");
+        assert_eq!(ValueFormat::Signed.format_bits(&("1".to_string() + &"0".repeat(127))), i128::MIN.to_string());
+        assert_eq!(ValueFormat::Dec.format_bits("1110"), "14");
+    }
+
+    #[test]
+    fn ascii_shows_unprintable_bytes_as_dots() {
+        assert_eq!(ValueFormat::Ascii.format_bits("0100100001101001"), "\"Hi\"");
+        // Padded to whole bytes from the least significant bit
+        assert_eq!(ValueFormat::Ascii.format_bits("1000001"), "\"A\"");
+        assert_eq!(ValueFormat::Ascii.format_bits("0000000101000001"), "\".A\"");
+    }
+
+    #[test]
+    fn undefined_bits_are_only_shown_in_hex_and_bin() {
+        for format in [ValueFormat::Dec, ValueFormat::Signed, ValueFormat::Ascii] {
+            assert_eq!(format.format_bits("01x1"), "UDF");
+            assert_eq!(format.format_bits("z001"), "UDF");
+        }
+        assert_eq!(ValueFormat::Bin.format_bits("01x1"), "0b01x1");
+        assert_eq!(ValueFormat::Hex.format_bits("01x1"), "0xx");
+    }
+
+    #[test]
+    fn signal_overrides_take_precedence_over_type_overrides() {
+        let overrides = FormatOverrides {
+            signals: HashMap::from([("io.out".to_string(), ValueFormat::Bin)]),
+            types: HashMap::from([("UInt".to_string(), ValueFormat::Hex), ("SInt<8>".to_string(), ValueFormat::Signed)])
+        };
+        assert_eq!(overrides.lookup(Some("io.out"), Some("UInt<8>")), Some(ValueFormat::Bin));
+        assert_eq!(overrides.lookup(Some("io.in"), Some("UInt<8>")), Some(ValueFormat::Hex));
+        assert_eq!(overrides.lookup(None, Some("SInt<8>")), Some(ValueFormat::Signed));
+        assert_eq!(overrides.lookup(None, Some("SInt<4>")), None);
+    }
+
+    #[test]
+    fn format_overrides_are_parsed() {
+        assert_eq!(parse_format_override("io.out.bits=HEX"), Ok(("io.out.bits".to_string(), ValueFormat::Hex)));
+        assert!(parse_format_override("io.out").is_err());
+        assert!(parse_format_override("io.out=oct").is_err());
+    }
+}
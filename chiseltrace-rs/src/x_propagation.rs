@@ -0,0 +1,161 @@
+use std::{collections::{hash_map::Entry, HashMap, VecDeque}, fmt::Display};
+
+use serde::Serialize;
+
+use crate::{pdg_spec::{ExportablePDG, PDGSpecEdgeKind, PDGSpecNodeKind, SimValue}, query::build_lookup_tables};
+
+/// How an undefined value entered the design
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum XOriginKind {
+    /// The state of a register before it was first written or reset
+    UninitializedRegister,
+    /// A connect without any sources, such as a connect to `DontCare`
+    DontCare,
+    /// An input of the design that the testbench did not drive
+    UndrivenInput,
+    /// A statement whose sources are all defined, e.g. an out-of-range dynamic index, or a source that the DPDG does
+    /// not contain
+    Unknown
+}
+
+impl Display for XOriginKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            XOriginKind::UninitializedRegister => "uninitialised register",
+            XOriginKind::DontCare => "DontCare connect",
+            XOriginKind::UndrivenInput => "undriven input",
+            XOriginKind::Unknown => "undefined value from defined sources"
+        };
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct XOrigin {
+    pub node: usize,
+    pub kind: XOriginKind,
+    /// The number of dependencies between the starting node and the origin
+    pub distance: u32
+}
+
+/// Whether a value has undefined (`x`) or high impedance (`z`) bits
+pub fn has_undefined_bits(value: &SimValue) -> bool {
+    value.bits.chars().any(|c| matches!(c, 'x' | 'X' | 'z' | 'Z'))
+}
+
+/// Follows the data and index dependencies of a node with an undefined value back to where the undefined value
+/// entered: the nodes with undefined values whose own sources are all defined. Returns no origins if the value of the
+/// node is defined. The origins are ordered from the earliest to the latest.
+pub fn trace_x_origins(pdg: &ExportablePDG, start: usize) -> Vec<XOrigin> {
+    let is_undefined = |idx: usize| pdg.vertices[idx].sim_data.as_ref().is_some_and(has_undefined_bits);
+    if !is_undefined(start) {
+        return vec![];
+    }
+
+    let (_, dep_to_edges, _) = build_lookup_tables(pdg);
+    let sources = |idx: usize| dep_to_edges.get(&(idx as u32)).into_iter().flatten()
+        .map(|e| &pdg.edges[*e])
+        .filter(|e| matches!(e.kind, PDGSpecEdgeKind::Data | PDGSpecEdgeKind::Index))
+        .map(|e| e.to as usize);
+
+    let mut distances = HashMap::from([(start, 0u32)]);
+    let mut queue = VecDeque::from([start]);
+    let mut origins = vec![];
+    while let Some(idx) = queue.pop_front() {
+        let distance = distances[&idx];
+        let mut undefined_sources = sources(idx).filter(|s| is_undefined(*s)).peekable();
+        if undefined_sources.peek().is_none() {
+            origins.push(XOrigin { node: idx, kind: origin_kind(pdg, idx, sources(idx).next().is_some()), distance });
+        }
+        for source in undefined_sources {
+            if let Entry::Vacant(entry) = distances.entry(source) {
+                entry.insert(distance + 1);
+                queue.push_back(source);
+            }
+        }
+    }
+
+    origins.sort_by_key(|o| (pdg.vertices[o.node].timestamp, o.node));
+    origins
+}
+
+fn origin_kind(pdg: &ExportablePDG, idx: usize, has_sources: bool) -> XOriginKind {
+    let node = &pdg.vertices[idx];
+    match node.kind {
+        PDGSpecNodeKind::DataDefinition if node.clocked => XOriginKind::UninitializedRegister,
+        PDGSpecNodeKind::IO if !has_sources => XOriginKind::UndrivenInput,
+        PDGSpecNodeKind::Connection if !has_sources => XOriginKind::DontCare,
+        _ => XOriginKind::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pdg_spec::{ExportablePDGEdge, ExportablePDGNode, SimValueKind};
+
+    use super::*;
+
+    fn node(name: &str, kind: PDGSpecNodeKind, clocked: bool, timestamp: i64, bits: &str) -> ExportablePDGNode {
+        let sim_data = SimValue { type_name: None, width: bits.len() as u32, bits: bits.into(), signed: false, kind: SimValueKind::Ground,
+            enum_variant: None, binary_point: None, fields: vec![], display: None };
+        ExportablePDGNode { file: "Top.scala".into(), line: 0, char: 0, end_char: None, name: name.into(), kind, clocked,
+            module_path: vec![], related_signal: None, assigns_to: None, predicate: None, provenance: vec![], sim_data: Some(sim_data),
+            sim_history: None, golden_mismatch: None, timestamp, is_chisel_assignment: true }
+    }
+
+    fn edge(from: u32, to: u32, kind: PDGSpecEdgeKind) -> ExportablePDGEdge {
+        ExportablePDGEdge { from, to, kind, clocked: false }
+    }
+
+    fn origins(pdg: &ExportablePDG, start: usize) -> Vec<(usize, XOriginKind, u32)> {
+        trace_x_origins(pdg, start).into_iter().map(|o| (o.node, o.kind, o.distance)).collect()
+    }
+
+    #[test]
+    fn undefined_values_are_traced_to_an_uninitialised_register() {
+        // out := a + b, a := reg, where only reg was never written
+        let pdg = ExportablePDG {
+            vertices: vec![
+                node("reg", PDGSpecNodeKind::DataDefinition, true, 0, "xxxx"),
+                node("a", PDGSpecNodeKind::Connection, false, 1, "xxxx"),
+                node("b", PDGSpecNodeKind::Connection, false, 1, "0001"),
+                node("out", PDGSpecNodeKind::Connection, false, 1, "xxxx"),
+            ],
+            edges: vec![edge(3, 1, PDGSpecEdgeKind::Data), edge(3, 2, PDGSpecEdgeKind::Data), edge(1, 0, PDGSpecEdgeKind::Data)]
+        };
+        assert_eq!(origins(&pdg, 3), vec![(0, XOriginKind::UninitializedRegister, 2)]);
+    }
+
+    #[test]
+    fn every_origin_is_found_from_the_earliest_to_the_latest() {
+        // out := mem(idx) + in, where idx is a DontCare and in is not driven by the testbench, and the memory read
+        // produces an undefined value from the defined address 5
+        let pdg = ExportablePDG {
+            vertices: vec![
+                node("out", PDGSpecNodeKind::Connection, false, 2, "xxxx"),
+                node("idx", PDGSpecNodeKind::Connection, false, 1, "xx"),
+                node("in", PDGSpecNodeKind::IO, false, 0, "zzzz"),
+                node("read", PDGSpecNodeKind::Connection, false, 2, "xxxx"),
+                node("addr", PDGSpecNodeKind::Connection, false, 2, "0101"),
+            ],
+            edges: vec![
+                edge(0, 1, PDGSpecEdgeKind::Index),
+                edge(0, 2, PDGSpecEdgeKind::Data),
+                edge(0, 3, PDGSpecEdgeKind::Data),
+                edge(3, 4, PDGSpecEdgeKind::Index),
+            ]
+        };
+        assert_eq!(origins(&pdg, 0), vec![
+            (2, XOriginKind::UndrivenInput, 1),
+            (1, XOriginKind::DontCare, 1),
+            (3, XOriginKind::Unknown, 1),
+        ]);
+    }
+
+    #[test]
+    fn defined_values_have_no_origin() {
+        let pdg = ExportablePDG { vertices: vec![node("out", PDGSpecNodeKind::Connection, false, 0, "0110")], edges: vec![] };
+        assert!(origins(&pdg, 0).is_empty());
+    }
+}
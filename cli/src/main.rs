@@ -1,11 +1,11 @@
-use std::{collections::HashSet, fs::{read_to_string, File}, io::BufWriter, path::Path};
+use std::{fs::File, io::{BufReader, BufWriter}, path::PathBuf};
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use chiseltrace_rs::{conversion::{dpdg_make_exportable, pdg_convert_to_source}, graphbuilder::GraphProcessingType, slicing::{pdg_slice, write_dynamic_slice, write_static_slice}, util::parse_criterion};
+use clap::{Parser, Subcommand, ValueEnum};
+use chiseltrace_rs::{conversion::{ConversionOptions, PassManager}, diff::{diff_dpdgs, Divergence, DivergenceKind}, export::{write_dot, write_gexf, write_graphml, DotOptions}, golden::GoldenValues, graphbuilder::GraphProcessingType, pdg_spec::ExportablePDG, pipeline::{build_trace, group_by_module, TraceConfig}, root_cause::{find_criterion, rank_root_causes}, slicing::{pdg_slice, write_dpdg_sarif, write_dynamic_slice, write_dynamic_slice_sarif, write_static_slice, write_static_slice_sarif}, util::{parse_criterion, read_source_files}, value_format::{parse_format_override, FormatOverrides, ValueFormat}, x_propagation::{has_undefined_bits, trace_x_origins}};
 use chiseltrace_rs::graphbuilder::{GraphBuilder, CriterionType};
 use chiseltrace_rs::pdg_spec::PDGSpec;
-use chiseltrace_rs::sim_data_injection::TywavesInterface;
-use serde::Deserialize;
+
+mod repl;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -25,6 +25,13 @@ enum Commands {
 
         #[clap(default_value = "slice.json")]
         output_path: String,
+
+        /// The format of the written slice
+        #[clap(long, value_enum, default_value_t = SliceFormat::Json)]
+        format: SliceFormat,
+
+        #[command(flatten)]
+        conversion: ConversionArgs,
     },
     /// Convert to a dynamic program dependency graph.
     DynPDG {
@@ -32,8 +39,8 @@ enum Commands {
         pdg_path: String,
         /// The path the the VCD file
         vcd_path: String,
-        /// Path to the HGLDD directory
-        hgldd_path: String,
+        /// Path to the HGLDD directory. If it cannot be loaded, the values are written without their Chisel types
+        hgldd_path: Option<PathBuf>,
         /// The statement that should be used for the program slicing.
         #[arg(
             value_parser = parse_criterion,
@@ -51,6 +58,23 @@ enum Commands {
 
         #[clap(default_value = "dynpdg.json")]
         output_path: String,
+
+        #[command(flatten)]
+        conversion: ConversionArgs,
+
+        #[command(flatten)]
+        value_formats: FormatArgs,
+
+        /// Attach the values of each node's signal in this many cycles before and after the node
+        #[arg(long, value_name = "CYCLES")]
+        value_history: Option<u32>,
+
+        /// A CSV or JSON file of (signal, cycle, value) entries dumped by a golden model. Nodes whose values differ are annotated
+        #[arg(long)]
+        golden_values: Option<String>,
+
+        #[command(flatten)]
+        output: OutputArgs,
     },
     
     DynSlice {
@@ -73,6 +97,10 @@ enum Commands {
 
         #[clap(long, default_value = "dynslice.json")]
         output_path: String,
+
+        /// The format of the written slice
+        #[clap(long, value_enum, default_value_t = SliceFormat::Json)]
+        format: SliceFormat,
     },
     /// Perform a conversion from FIRRTL PDG to Chisel PDG operation.
     Convert {
@@ -80,84 +108,460 @@ enum Commands {
         path: String,
         #[clap(default_value = "converted_pdg.json")]
         output_path: String,
+
+        #[command(flatten)]
+        conversion: ConversionArgs,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Build the DPDG of a criterion like the viewer does and write it to a file, without a display.
+    Trace(TraceArgs),
+    /// Build or load a DPDG once and query it interactively.
+    #[command(subcommand)]
+    Repl(ReplSource),
+    /// Rank the nodes that most likely caused the wrong value of a node in a DPDG.
+    RootCause {
+        /// The path to the DPDG, as written by the trace or dyn-pdg commands
+        path: String,
+
+        /// A DPDG of a passing run, with the same criterion. Its values are taken as the expected values.
+        #[arg(long)]
+        golden: Option<String>,
+
+        /// The node with the wrong value. Defaults to the criterion of the DPDG.
+        #[arg(long)]
+        node: Option<usize>,
+
+        /// The number of candidates to show
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+
+        #[command(flatten)]
+        value_formats: FormatArgs,
+    },
+    /// Compare the DPDG of a failing run with the DPDG of a passing run, traced from the same criterion.
+    Diff {
+        /// The DPDG of the failing run
+        failing: String,
+        /// The DPDG of the passing run, in the same representation
+        passing: String,
+
+        /// The number of divergences to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Also write all divergences to a JSON file
+        #[arg(long)]
+        output_path: Option<String>,
+
+        #[command(flatten)]
+        value_formats: FormatArgs,
+    },
+    /// Find where the undefined (X) value of a node in a DPDG entered the design.
+    XOrigins {
+        /// The path to the DPDG, as written by the trace or dyn-pdg commands
+        path: String,
+
+        /// The node with the undefined value. Defaults to the criterion of the DPDG.
+        #[arg(long)]
+        node: Option<usize>,
+
+        #[command(flatten)]
+        value_formats: FormatArgs,
+    },
+    /// Write a converted PDG or DPDG, as written by the other commands, in another format.
+    Export {
+        /// The path to the converted PDG or DPDG
+        path: String,
+        output_path: String,
+
+        #[command(flatten)]
+        output: OutputArgs,
     }
 }
 
+/// Where the REPL gets its DPDG from
+#[derive(Subcommand, Debug)]
+enum ReplSource {
+    /// Load a DPDG written by the trace or dyn-pdg commands
+    Load {
+        path: String,
+
+        #[command(flatten)]
+        value_formats: FormatArgs,
+    },
+    /// Build the DPDG like the trace command does
+    Build {
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+
+        #[command(flatten)]
+        value_formats: FormatArgs,
+    }
+}
+
+/// The pipeline and the options of the viewer, followed by the output options
+#[derive(clap::Args, Debug)]
+struct TraceArgs {
+    #[command(flatten)]
+    pipeline: PipelineArgs,
+
+    #[command(flatten)]
+    value_formats: FormatArgs,
+
+    #[arg(short, long, default_value = "trace.json")]
+    output_path: String,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+/// The options of the viewer that determine the traced DPDG
+#[derive(clap::Args, Debug)]
+struct PipelineArgs {
+    /// Slicing criterion (e.g. the statement that will be backtraced)
+    #[arg(
+        short,
+        long,
+        value_parser = parse_criterion,
+        help = "Criterion in format 'type:value' (e.g., 'statement:connect_io.a')"
+    )]
+    slice_criterion: CriterionType,
+
+    /// Path to the program dependency graph exported by chisel
+    #[arg(short, long)]
+    pdg_path: String,
+
+    /// Path to the VCD file
+    #[arg(short, long)]
+    vcd_path: String,
+
+    /// Path to the HGLDD directory. Without it, simulation values are written without their Chisel types
+    #[arg(long)]
+    hgldd_path: Option<String>,
+
+    /// The name of the top-level module
+    #[arg(short, long)]
+    top_module: String,
+
+    /// Specifies additional scopes that will be used while processing.
+    #[clap(short, long, value_delimiter = ' ', num_args = 1..)]
+    extra_scopes: Option<Vec<String>>,
+
+    /// Sets a maximumum amount of timesteps to analyse
+    #[arg(long)]
+    max_timesteps: Option<u64>,
+
+    /// Only trace data dependencies
+    #[arg(long)]
+    data_only: Option<bool>,
+
+    /// Collapses the nodes in submodules into one node per instance, like the viewer does on startup
+    #[arg(long)]
+    hier_grouping: Option<bool>,
+
+    /// Writes the FIRRTL representation instead of the Chisel representation
+    #[arg(long)]
+    fir: Option<bool>,
+
+    #[command(flatten)]
+    conversion: ConversionArgs,
+
+    /// Attach the values of each node's signal in this many cycles before and after the node
+    #[arg(long, value_name = "CYCLES")]
+    value_history: Option<u32>,
+
+    /// A CSV or JSON file of (signal, cycle, value) entries dumped by a golden model. Nodes whose values differ are annotated
+    #[arg(long)]
+    golden_values: Option<String>,
+}
+
+impl PipelineArgs {
+    /// Runs the pipeline of the viewer and returns the graph it would show on startup
+    fn build(&self) -> Result<ExportablePDG> {
+        let config = TraceConfig { criterion: self.slice_criterion.clone(),
+            pdg_path: PathBuf::from(&self.pdg_path),
+            vcd_path: PathBuf::from(&self.vcd_path),
+            hgldd_path: self.hgldd_path.as_ref().map(PathBuf::from),
+            top_module: self.top_module.clone(),
+            extra_scopes: self.extra_scopes.clone().unwrap_or(vec![]),
+            max_timesteps: self.max_timesteps,
+            data_only: self.data_only.unwrap_or(false),
+            conversion_passes: self.conversion.pass_manager()?.pass_names().into_iter().map(String::from).collect(),
+            value_history: self.value_history,
+            golden_values: self.golden_values.as_ref().map(PathBuf::from)
+        };
+        let trace = build_trace(&config)?;
+
+        let dpdg = if self.fir.unwrap_or(false) { trace.firrtl } else { trace.chisel };
+        if self.hier_grouping.unwrap_or(false) {
+            Ok(group_by_module(&dpdg))
+        } else {
+            Ok(dpdg)
+        }
+    }
+}
+
+/// Options for writing (D)PDGs
+#[derive(clap::Args, Debug)]
+struct OutputArgs {
+    /// The format of the written graph
+    #[arg(long, value_enum, default_value_t = GraphFormat::Json)]
+    format: GraphFormat,
+
+    /// In DOT output, also cluster the nodes of each timestamp by module instance
+    #[arg(long)]
+    cluster_modules: bool,
+}
+
+impl OutputArgs {
+    fn write(&self, pdg: &ExportablePDG, formats: FormatOverrides, path: &str) -> Result<()> {
+        match self.format {
+            GraphFormat::Json => {
+                let writer = BufWriter::new(File::create(path)?);
+                serde_json::to_writer_pretty(writer, pdg)?;
+            }
+            GraphFormat::Sarif => write_dpdg_sarif(pdg, path)?,
+            GraphFormat::Dot => write_dot(pdg, &DotOptions { cluster_modules: self.cluster_modules, formats }, path)?,
+            GraphFormat::Graphml => write_graphml(pdg, &formats, path)?,
+            GraphFormat::Gexf => write_gexf(pdg, &formats, path)?
+        }
+        Ok(())
+    }
+}
+
+/// Options for the conversion from FIRRTL representation to Chisel representation
+#[derive(clap::Args, Debug)]
+struct ConversionArgs {
+    /// Comma-separated list of conversion passes to run, in order. Defaults to squash-index-edges, group-statements,
+    /// self-dependencies, merge-groups, dedup-lookup-tables, merge-index-groups, reconstruct-predicates
+    #[arg(long, value_delimiter = ',')]
+    passes: Option<Vec<String>>,
+
+    /// Comma-separated list of conversion passes that will not be run
+    #[arg(long, value_delimiter = ',')]
+    disable_passes: Vec<String>,
+}
+
+impl ConversionArgs {
+    fn pass_manager(&self) -> Result<PassManager> {
+        PassManager::from_config(self.passes.as_deref(), &self.disable_passes)
+    }
+}
+
+/// Display formats for simulation values. Formatted values are written to the `display` field of the value.
+#[derive(clap::Args, Debug)]
+struct FormatArgs {
+    /// Display format of a signal or one of its fields, as 'signal=format' (e.g. 'io.addr=hex').
+    /// Formats are hex, bin, dec, signed and ascii
+    #[arg(long = "format-signal", value_name = "SIGNAL=FORMAT", value_parser = parse_format_override)]
+    signals: Vec<(String, ValueFormat)>,
+
+    /// Display format of all signals of a type, as 'type=format' (e.g. 'SInt=signed' or 'UInt<32>=hex')
+    #[arg(long = "format-type", value_name = "TYPE=FORMAT", value_parser = parse_format_override)]
+    types: Vec<(String, ValueFormat)>,
+}
+
+impl FormatArgs {
+    fn overrides(&self) -> FormatOverrides {
+        FormatOverrides { signals: self.signals.iter().cloned().collect(), types: self.types.iter().cloned().collect() }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SliceFormat {
+    /// The ChiselTrace slice format
+    Json,
+    /// Static Analysis Results Interchange Format, understood by most editors and code review tools
+    Sarif
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum GraphFormat {
+    /// The graph as read by the viewer
+    Json,
+    /// The statements and the timestamps at which they were active, as SARIF results
+    Sarif,
+    /// A Graphviz graph that is styled like the viewer, with the nodes clustered by timestamp
+    Dot,
+    /// GraphML with the node fields as typed attributes, e.g. for yEd
+    Graphml,
+    /// GEXF with the node fields as typed attributes and the viewer's colours, e.g. for Gephi
+    Gexf
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
-    let argpath = match &args.command {
-        Commands::Slice { path, .. } => path,
-        Commands::Convert { path , ..} => path,
-        Commands::DynPDG { pdg_path, .. } => pdg_path,
-        Commands::DynSlice { pdg_path, ..} => pdg_path
-    };
-    let buf = read_to_string(argpath)?;
-    let mut deser = serde_json::Deserializer::from_str(buf.as_str());
-    deser.disable_recursion_limit();
-    let pdg_raw = PDGSpec::deserialize(&mut deser)?;
-
     match &args.command {
-        Commands::Slice { slice_criterion, output_path, .. } => {
-            let sliced = pdg_slice(pdg_raw, slice_criterion)?;
-            let converted = pdg_convert_to_source(sliced.into(), true, false);
-            write_static_slice(&converted, output_path)?;
+        Commands::Slice { path, slice_criterion, output_path, format, conversion } => {
+            let sliced: ExportablePDG = pdg_slice(PDGSpec::from_file(path)?, slice_criterion)?.into();
+            let source_files = read_source_files(sliced.vertices.iter().map(|v| v.file.as_str()));
+            let converted = conversion.pass_manager()?.run(sliced, &ConversionOptions { verbose_name: true, is_dpdg: false, source_files });
+            match format {
+                SliceFormat::Json => write_static_slice(&converted, output_path)?,
+                SliceFormat::Sarif => write_static_slice_sarif(&converted, output_path)?
+            }
         },
-        Commands::Convert { output_path, .. } => {
-            let converted = pdg_convert_to_source(pdg_raw.into(), true, false);
-            let output_file = File::create(output_path)?;
-            let writer = BufWriter::new(output_file);
-        
-            serde_json::to_writer_pretty(writer, &converted)?;
+        Commands::Convert { path, output_path, conversion, output } => {
+            let pdg: ExportablePDG = PDGSpec::from_file(path)?.into();
+            let source_files = read_source_files(pdg.vertices.iter().map(|v| v.file.as_str()));
+            let converted = conversion.pass_manager()?.run(pdg, &ConversionOptions { verbose_name: true, is_dpdg: false, source_files });
+            output.write(&converted, FormatOverrides::default(), output_path)?;
         },
-        Commands::DynPDG { pdg_path:_, vcd_path, hgldd_path, slice_criterion, max_timesteps, top_module, extra_scopes, output_path } => {
-            let max_timesteps = max_timesteps.map(|x| x as i64);
-            // let sliced = pdg_slice(pdg_raw, slice_criterion)?;
-            let sliced  = pdg_raw;
-            // write_pdg(&sliced, "out_pdg.json")?;
-            // println!("{:#?}", args);
-
-            println!("Starting dynamic PDG building");
-            let mut builder = GraphBuilder::new(vcd_path, extra_scopes.clone().unwrap_or(vec![]), sliced)?;
-            let dpdg = builder.process(&slice_criterion, max_timesteps, GraphProcessingType::Normal)?;
-
-            println!("Making DPDG exportable");
-            let dpdg = dpdg_make_exportable(dpdg);
-            
-            println!("Converting to source representation");
-            let mut converted_pdg = pdg_convert_to_source(dpdg, false, true);
-
-            println!("Adding tywaves info");
-            let tywaves = TywavesInterface::new(Path::new(hgldd_path),
-                vec!["TOP".into(), "svsimTestbench".into(), "dut".into()], &top_module)?;
-            
-            let tywaves_vcd_path = tywaves.vcd_rewrite(Path::new(vcd_path))?;
-            println!("VCD rewritten");
-            tywaves.inject_sim_data(&mut converted_pdg, &tywaves_vcd_path)?;
-
-            let mut lines = HashSet::new();
-            for vert in &converted_pdg.vertices {
-                if vert.timestamp >= 80 {
-                    lines.insert((vert.file.clone(), vert.line));
-                }
+        Commands::DynPDG { pdg_path, vcd_path, hgldd_path, slice_criterion, max_timesteps, top_module, extra_scopes, output_path, conversion, value_formats, value_history, golden_values, output } => {
+            // The same pipeline as the trace command and the viewer, so the values are injected before the conversion
+            let config = TraceConfig { criterion: slice_criterion.clone(),
+                pdg_path: PathBuf::from(pdg_path),
+                vcd_path: PathBuf::from(vcd_path),
+                hgldd_path: hgldd_path.clone(),
+                top_module: top_module.clone(),
+                extra_scopes: extra_scopes.clone().unwrap_or(vec![]),
+                max_timesteps: *max_timesteps,
+                data_only: false,
+                conversion_passes: conversion.pass_manager()?.pass_names().into_iter().map(String::from).collect(),
+                value_history: *value_history,
+                golden_values: golden_values.as_ref().map(PathBuf::from)
+            };
+            let mut converted_pdg = build_trace(&config)?.chisel;
+            let formats = value_formats.overrides();
+            if golden_values.is_some() {
+                print_earliest_mismatch(&converted_pdg, &formats);
             }
-            println!("Unique source lines in DPDG: {}", lines.len());
+            formats.apply(&mut converted_pdg);
+
             println!("Num verts: {}, num edges: {}", converted_pdg.vertices.len(), converted_pdg.edges.len());
-    
-            let f = File::create(&output_path)?;
-            let writer = BufWriter::new(f);
-            serde_json::to_writer_pretty(writer, &converted_pdg)?;
+
+            output.write(&converted_pdg, formats, output_path)?;
         }
-        Commands::DynSlice { pdg_path:_, vcd_path, slice_criterion, max_timesteps, extra_scopes, output_path } => {
-            let sliced  = pdg_raw;
+        Commands::DynSlice { pdg_path, vcd_path, slice_criterion, max_timesteps, extra_scopes, output_path, format } => {
+            let sliced = PDGSpec::from_file(pdg_path)?;
             let max_timesteps = max_timesteps.map(|x| x as i64);
 
             println!("Starting dynamic PDG building");
             let mut builder = GraphBuilder::new(vcd_path, extra_scopes.clone().unwrap_or(vec![]), sliced)?;
             let dpdg = builder.process(&slice_criterion, max_timesteps.clone(), GraphProcessingType::Full)?;
 
-            write_dynamic_slice(&dpdg, output_path)?;
+            match format {
+                SliceFormat::Json => write_dynamic_slice(&dpdg, output_path)?,
+                SliceFormat::Sarif => write_dynamic_slice_sarif(&dpdg, output_path)?
+            }
+        }
+        // The trace pipeline reads the PDG itself
+        Commands::Trace(trace_args) => trace(trace_args)?,
+        Commands::Export { path, output_path, output } => output.write(&read_exported(path)?, FormatOverrides::default(), output_path)?,
+        Commands::Repl(ReplSource::Load { path, value_formats }) => repl::run(read_exported(path)?, value_formats.overrides())?,
+        Commands::Repl(ReplSource::Build { pipeline, value_formats }) => repl::run(pipeline.build()?, value_formats.overrides())?,
+        Commands::RootCause { path, golden, node, limit, value_formats } => {
+            root_cause(path, golden.as_deref(), *node, *limit, &value_formats.overrides())?;
         }
+        Commands::Diff { failing, passing, limit, output_path, value_formats } => {
+            diff(failing, passing, *limit, output_path.as_deref(), &value_formats.overrides())?;
+        }
+        Commands::XOrigins { path, node, value_formats } => x_origins(path, *node, &value_formats.overrides())?,
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+fn trace(args: &TraceArgs) -> Result<()> {
+    let mut dpdg = args.pipeline.build()?;
+    let formats = args.value_formats.overrides();
+    formats.apply(&mut dpdg);
+    println!("DPDG has {} nodes and {} edges", dpdg.vertices.len(), dpdg.edges.len());
+    if args.pipeline.golden_values.is_some() {
+        print_earliest_mismatch(&dpdg, &formats);
+    }
+
+    args.output.write(&dpdg, formats, &args.output_path)
+}
+
+fn print_earliest_mismatch(dpdg: &ExportablePDG, formats: &FormatOverrides) {
+    let Some(idx) = dpdg.vertices.iter().position(|v| v.golden_mismatch.as_ref().is_some_and(|m| m.earliest)) else {
+        println!("The graph has no mismatch with the golden reference that the criterion depends on");
+        return;
+    };
+    let expected = dpdg.vertices[idx].golden_mismatch.as_ref().map(|m| ValueFormat::Dec.format_bits(&m.expected)).unwrap_or_default();
+    println!("Earliest mismatch with the golden reference: {} (expected {})", repl::describe(dpdg, idx, formats), expected);
+}
+
+fn root_cause(path: &str, golden: Option<&str>, node: Option<usize>, limit: usize, formats: &FormatOverrides) -> Result<()> {
+    let dpdg = read_exported(path)?;
+    let golden = golden.map(|path| read_exported(path).map(|pdg| GoldenValues::from_pdg(&pdg))).transpose()?;
+    let criterion = match node {
+        Some(idx) if idx >= dpdg.vertices.len() => anyhow::bail!("There is no node {}", idx),
+        Some(idx) => idx,
+        None => find_criterion(&dpdg).ok_or_else(|| anyhow::anyhow!("The DPDG is empty"))?
+    };
+    println!("Root cause candidates for {}", repl::describe(&dpdg, criterion, formats));
+
+    let candidates = rank_root_causes(&dpdg, criterion, golden.as_ref());
+    if candidates.is_empty() {
+        println!("No candidates found");
+    }
+    for (rank, candidate) in candidates.iter().take(limit).enumerate() {
+        println!("{:>3}. (score {}) {}", rank + 1, candidate.score, repl::describe(&dpdg, candidate.node, formats));
+        for reason in &candidate.reasons {
+            println!("       - {}", reason);
+        }
+    }
+    Ok(())
+}
+
+fn x_origins(path: &str, node: Option<usize>, formats: &FormatOverrides) -> Result<()> {
+    let dpdg = read_exported(path)?;
+    let start = match node {
+        Some(idx) if idx >= dpdg.vertices.len() => anyhow::bail!("There is no node {}", idx),
+        Some(idx) => idx,
+        None => find_criterion(&dpdg).ok_or_else(|| anyhow::anyhow!("The DPDG is empty"))?
+    };
+    if !dpdg.vertices[start].sim_data.as_ref().is_some_and(has_undefined_bits) {
+        anyhow::bail!("The value of node {} is not undefined", start);
+    }
+
+    println!("Origins of the undefined value of {}", repl::describe(&dpdg, start, formats));
+    for origin in trace_x_origins(&dpdg, start) {
+        println!("  {}: {} ({} dependencies away)", origin.kind, repl::describe(&dpdg, origin.node, formats), origin.distance);
+    }
+    Ok(())
+}
+
+fn diff(failing_path: &str, passing_path: &str, limit: usize, output_path: Option<&str>, formats: &FormatOverrides) -> Result<()> {
+    let failing = read_exported(failing_path)?;
+    let passing = read_exported(passing_path)?;
+    let diff = diff_dpdgs(&failing, &passing);
+    if let Some(output_path) = output_path {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(output_path)?), &diff)?;
+    }
+
+    let count = |f: fn(&DivergenceKind) -> bool| diff.divergences.iter().filter(|d| f(&d.kind)).count();
+    println!("Matched {} activations, found {} divergences ({} values, {} control decisions, {} extra and {} missing activations)",
+        diff.matched.len(), diff.divergences.len(),
+        count(|k| matches!(k, DivergenceKind::Value { .. })), count(|k| matches!(k, DivergenceKind::ControlDecision { .. })),
+        count(|k| *k == DivergenceKind::ExtraActivation), count(|k| *k == DivergenceKind::MissingActivation));
+    if !diff.unaligned_failing.is_empty() || !diff.unaligned_passing.is_empty() {
+        println!("Not compared: {} failing and {} passing nodes at cycles that only one of the traces covers",
+            diff.unaligned_failing.len(), diff.unaligned_passing.len());
+    }
+
+    let describe = |d: &Divergence| {
+        let node = match (d.failing, d.passing) {
+            (Some(idx), _) => repl::describe(&failing, idx, formats),
+            (None, Some(idx)) => format!("passing run {}", repl::describe(&passing, idx, formats)),
+            (None, None) => unreachable!()
+        };
+        format!("cycle {}: {}: {}", d.cycle, node, d.kind)
+    };
+    if let Some(earliest) = diff.earliest() {
+        println!("Earliest divergence at {}", describe(earliest));
+    }
+    for d in diff.divergences.iter().take(limit) {
+        println!("  {}", describe(d));
+    }
+    Ok(())
+}
+
+/// Reads a (D)PDG as written by the convert, dyn-pdg and trace commands
+fn read_exported(path: &str) -> Result<ExportablePDG> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
@@ -0,0 +1,162 @@
+use std::{collections::{HashMap, HashSet}, io::{self, BufRead, Write}};
+
+use anyhow::{anyhow, Result};
+use chiseltrace_rs::{pdg_spec::ExportablePDG, query::{build_lookup_tables, dependency_path, reachable_from, signal_nodes, signal_value}, translation::{interpret_node_value, interpret_tywaves_value, node_label}, value_format::{signal_key, FormatOverrides}};
+
+const HELP: &str = "Commands:
+  info N           Show node N
+  deps N           The nodes that node N depends on
+  users N          The nodes that depend on node N
+  nodes S [T]      The nodes of signal S, optionally only those at timestamp T
+  providers S T    The nodes that signal S depended on at timestamp T
+  value S T        The value of signal S at timestamp T
+  path A B         The shortest chain of dependencies from node A to node B
+  root N           Only consider node N and the nodes it depends on, like setting the graph head in the viewer
+  reset            Consider all nodes again
+  help             Show this message
+  quit             Exit";
+
+/// A DPDG with the lookup tables that the queries need, as kept by the viewer
+struct Session {
+    dpdg: ExportablePDG,
+    dep_to_edges: HashMap<u32, Vec<usize>>,
+    prov_to_edges: HashMap<u32, Vec<usize>>,
+    /// The nodes that queries consider. All nodes, unless the graph has been re-rooted.
+    shown_ids: HashSet<usize>,
+    formats: FormatOverrides
+}
+
+/// Answers queries about a DPDG read from stdin, until `quit` or the end of the input
+pub fn run(dpdg: ExportablePDG, formats: FormatOverrides) -> Result<()> {
+    let (_, dep_to_edges, prov_to_edges) = build_lookup_tables(&dpdg);
+    let shown_ids = (0..dpdg.vertices.len()).collect();
+    let mut session = Session { dpdg, dep_to_edges, prov_to_edges, shown_ids, formats };
+    println!("DPDG has {} nodes and {} edges. Type 'help' for a list of commands.", session.dpdg.vertices.len(), session.dpdg.edges.len());
+
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            break;
+        };
+        let line = line?;
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => (),
+            ["quit"] | ["exit"] => break,
+            ["help"] => println!("{}", HELP),
+            _ => if let Err(e) = session.execute(&words) {
+                println!("Error: {}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Session {
+    fn execute(&mut self, words: &[&str]) -> Result<()> {
+        match words {
+            ["info", n] => {
+                let node = &self.dpdg.vertices[self.node_id(n)?];
+                println!("{}", node_label(node, &self.formats));
+                println!("  kind:        {:?}{}", node.kind, if node.clocked { " (clocked)" } else { "" });
+                println!("  location:    {}:{}:{}", node.file, node.line, node.char);
+                println!("  module:      {}", node.module_path.iter().filter(|p| !p.is_empty()).cloned().collect::<Vec<_>>().join("."));
+                println!("  timestamp:   {}", node.timestamp);
+                if let Some(signal) = &node.related_signal {
+                    println!("  signal:      {}", signal_key(signal));
+                }
+                if let Some(value) = interpret_node_value(node, &self.formats) {
+                    println!("  value:       {}", value.label().replace('\n', "\n               "));
+                }
+            }
+            ["deps", n] => self.print_edges(self.node_id(n)?, true),
+            ["users", n] => self.print_edges(self.node_id(n)?, false),
+            ["nodes", signal] => self.print_nodes(&self.signal_nodes(signal, None)),
+            ["nodes", signal, t] => self.print_nodes(&self.signal_nodes(signal, Some(t.parse()?))),
+            ["providers", signal, t] => {
+                let nodes = self.signal_nodes(signal, Some(t.parse()?));
+                if nodes.is_empty() {
+                    println!("No nodes of {} at timestamp {}", signal, t);
+                }
+                for idx in nodes {
+                    println!("{}", self.describe(idx));
+                    self.print_edges(idx, true);
+                }
+            }
+            ["value", signal, t] => {
+                let value = signal_value(&self.dpdg, signal, t.parse()?)
+                    .ok_or_else(|| anyhow!("No value of {} is known at timestamp {}", signal, t))?;
+                println!("{}", interpret_tywaves_value(&value, Some(signal), &self.formats).label());
+            }
+            ["path", a, b] => {
+                let (from, to) = (self.node_id(a)?, self.node_id(b)?);
+                match dependency_path(&self.dpdg, &self.dep_to_edges, from, to) {
+                    Some(path) => self.print_nodes(&path),
+                    None => println!("Node {} does not depend on node {}", from, to)
+                }
+            }
+            ["root", n] => {
+                let id = self.node_id(n)?;
+                self.shown_ids = reachable_from(&self.dpdg, &self.dep_to_edges, id);
+                println!("Considering {} nodes", self.shown_ids.len());
+            }
+            ["reset"] => {
+                self.shown_ids = (0..self.dpdg.vertices.len()).collect();
+                println!("Considering {} nodes", self.shown_ids.len());
+            }
+            _ => anyhow::bail!("Unknown command '{}', type 'help' for a list of commands", words.join(" "))
+        }
+        Ok(())
+    }
+
+    /// Parses a node ID, which has to refer to a node that is considered
+    fn node_id(&self, s: &str) -> Result<usize> {
+        let id = s.parse::<usize>().map_err(|_| anyhow!("'{}' is not a node ID", s))?;
+        if id >= self.dpdg.vertices.len() {
+            anyhow::bail!("There is no node {}", id);
+        }
+        if !self.shown_ids.contains(&id) {
+            anyhow::bail!("Node {} is not reachable from the root, use 'reset' to consider all nodes", id);
+        }
+        Ok(id)
+    }
+
+    fn signal_nodes(&self, signal: &str, timestamp: Option<i64>) -> Vec<usize> {
+        signal_nodes(&self.dpdg, signal, timestamp).into_iter()
+            .filter(|idx| self.shown_ids.contains(idx))
+            .collect()
+    }
+
+    /// Prints the dependencies of a node, or the nodes that depend on it
+    fn print_edges(&self, id: usize, dependencies: bool) {
+        let edges = if dependencies { self.dep_to_edges.get(&(id as u32)) } else { self.prov_to_edges.get(&(id as u32)) };
+        for edge in edges.into_iter().flatten().map(|e| &self.dpdg.edges[*e]) {
+            let other = if dependencies { edge.to } else { edge.from } as usize;
+            if self.shown_ids.contains(&other) {
+                println!("  {:?}{} {}", edge.kind, if edge.clocked { " (clocked)" } else { "" }, self.describe(other));
+            }
+        }
+    }
+
+    fn print_nodes(&self, nodes: &[usize]) {
+        for idx in nodes {
+            println!("{}", self.describe(*idx));
+        }
+    }
+
+    fn describe(&self, idx: usize) -> String {
+        describe(&self.dpdg, idx, &self.formats)
+    }
+}
+
+/// A one-line description of a node: its ID, timestamp, label, value and location
+pub fn describe(dpdg: &ExportablePDG, idx: usize, formats: &FormatOverrides) -> String {
+    let node = &dpdg.vertices[idx];
+    let value = interpret_node_value(node, formats)
+        .map(|v| format!(" = {}", v.value))
+        .unwrap_or_default();
+    format!("[{}] t={} {}{} ({}:{})", idx, node.timestamp, node_label(node, formats), value, node.file, node.line)
+}
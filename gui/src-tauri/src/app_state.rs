@@ -1,15 +1,18 @@
 use std::{collections::{HashMap, HashSet}, path::PathBuf, sync::{Arc, RwLock, Weak}};
 
-use chiseltrace_rs::{graphbuilder::CriterionType, pdg_spec::{ExportablePDG, ExportablePDGNode}};
+use chiseltrace_rs::{graphbuilder::CriterionType, pdg_spec::{ExportablePDG, ExportablePDGNode}, pipeline::TraceConfig, value_format::{FormatOverrides, ValueFormat}};
+use serde::{Deserialize, Serialize};
 
 pub struct AppState {
     pub pdg_config: Option<PDGConfig>,
-    pub graph: Option<ViewableGraph>
+    pub graph: Option<ViewableGraph>,
+    /// The display formats for simulation values chosen by the user. They are kept for the rest of the session.
+    pub value_formats: FormatOverrides
 }
 
 impl AppState {
     pub fn new() -> Self {
-        AppState { pdg_config: None, graph: None }
+        AppState { pdg_config: None, graph: None, value_formats: FormatOverrides::default() }
     }
 }
 
@@ -18,13 +21,36 @@ pub struct PDGConfig {
     pub criterion: CriterionType,
     pub pdg_path: PathBuf,
     pub vcd_path: PathBuf,
-    pub hgldd_path: PathBuf,
+    pub hgldd_path: Option<PathBuf>,
     pub top_module: String,
     pub extra_scopes: Vec<String>,
     pub max_timesteps: Option<u64>,
     pub data_only: bool,
     pub group_nodes: bool,
-    pub fir_repr: bool
+    pub fir_repr: bool,
+    pub conversion_passes: Vec<String>,
+    /// The DPDG of a passing run, to compare the shown graph with
+    pub passing_dpdg: Option<PathBuf>,
+    pub golden_values: Option<PathBuf>,
+    /// The number of cycles before and after each node for which the value of its signal is kept, for the mini-waveforms
+    pub value_history: Option<u32>
+}
+
+impl PDGConfig {
+    pub fn trace_config(&self) -> TraceConfig {
+        TraceConfig { criterion: self.criterion.clone(),
+            pdg_path: self.pdg_path.clone(),
+            vcd_path: self.vcd_path.clone(),
+            hgldd_path: self.hgldd_path.clone(),
+            top_module: self.top_module.clone(),
+            extra_scopes: self.extra_scopes.clone(),
+            max_timesteps: self.max_timesteps,
+            data_only: self.data_only,
+            conversion_passes: self.conversion_passes.clone(),
+            value_history: self.value_history,
+            golden_values: self.golden_values.clone()
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -61,5 +87,67 @@ pub struct ViewableGraph {
     pub should_group_nodes: bool,
     pub node_hierarchy: Option<Vec<Arc<RwLock<GraphNodeHierarchy>>>>,
     pub node_hierarchy_lookup: Option<HashMap<usize, Arc<RwLock<GraphNodeHierarchy>>>>,
-    pub current_hier_dpdg: Option<HierarchicalGraph>
+    pub current_hier_dpdg: Option<HierarchicalGraph>,
+    /// The representation that `dpdg` is in
+    pub representation: GraphRepresentation,
+    /// The DPDG in the representation that is not shown. Both are kept, so the viewer can switch between them.
+    pub other_dpdg: ExportablePDG,
+    /// For every node of the FIRRTL DPDG, the node of the Chisel DPDG it was merged into
+    pub firrtl_to_chisel: Vec<Option<usize>>,
+    /// The node that was last set as the head of the graph
+    pub head: Option<usize>,
+    /// Nodes that the last analysis pointed out, with the reason that is shown for them in the viewer. Mismatches with the
+    /// golden reference are not part of these, they are always shown.
+    pub highlights: HashMap<usize, String>
+}
+
+/// The highlight of a node whose value differs from the golden reference
+fn golden_highlight(node: &ExportablePDGNode) -> Option<String> {
+    let mismatch = node.golden_mismatch.as_ref()?;
+    let prefix = if mismatch.earliest { "Earliest mismatch with the golden reference" } else { "Mismatch with the golden reference" };
+    Some(format!("{}: expected {}", prefix, ValueFormat::Dec.format_bits(&mismatch.expected)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphRepresentation {
+    Firrtl,
+    Chisel
+}
+
+impl ViewableGraph {
+    /// The text that a node is highlighted with: the reason the last analysis pointed it out, and its mismatch with the golden reference
+    pub fn highlight(&self, idx: usize) -> Option<String> {
+        let golden = self.dpdg.vertices.get(idx).and_then(golden_highlight);
+        match (self.highlights.get(&idx), golden) {
+            (Some(highlight), Some(golden)) => Some(format!("{}. {}", highlight, golden)),
+            (highlight, golden) => highlight.cloned().or(golden)
+        }
+    }
+
+    /// Finds the node in the other representation that corresponds to a node in the shown representation.
+    /// A Chisel node maps to the FIRRTL statement it is named after, or its first FIRRTL statement if there is no such statement.
+    pub fn corresponding_node(&self, id: usize) -> Option<usize> {
+        match self.representation {
+            GraphRepresentation::Firrtl => self.firrtl_to_chisel.get(id).copied().flatten(),
+            GraphRepresentation::Chisel => {
+                let node = self.dpdg.vertices.get(id)?;
+                let primary = node.provenance.iter().find(|p| p.name == node.name).or(node.provenance.first())?;
+                Some(primary.index as usize)
+            }
+        }
+    }
+}
+
+/// Maps every FIRRTL node to the Chisel node that it was merged into, using the provenance of the Chisel nodes.
+pub fn map_firrtl_to_chisel(firrtl: &ExportablePDG, chisel: &ExportablePDG) -> Vec<Option<usize>> {
+    let mut mapping = vec![None; firrtl.vertices.len()];
+    for (idx, v) in chisel.vertices.iter().enumerate() {
+        for p in &v.provenance {
+            if let Some(m) = mapping.get_mut(p.index as usize) {
+                *m = Some(idx);
+            }
+        }
+    }
+    mapping
 }
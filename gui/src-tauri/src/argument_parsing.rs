@@ -2,7 +2,7 @@ use std::path::Path;
 
 use clap::Parser;
 use anyhow::Result;
-use chiseltrace_rs::{graphbuilder::CriterionType, util::parse_criterion};
+use chiseltrace_rs::{conversion::PassManager, graphbuilder::CriterionType, util::parse_criterion};
 
 use crate::errors;
 
@@ -27,9 +27,9 @@ pub struct Args {
     #[arg(short, long)]
     pub vcd_path: String,
 
-    /// Path to the HGLDD directory
+    /// Path to the HGLDD directory. Without it, simulation values are shown without their Chisel types
     #[arg(long)]
-    pub hgldd_path: String,
+    pub hgldd_path: Option<String>,
 
     /// The name of the top-level module
     #[arg(short, long)]
@@ -51,9 +51,30 @@ pub struct Args {
     #[arg(long)]
     pub hier_grouping: Option<bool>,
 
-    /// Disables conversion to Chisel representation
+    /// Shows the FIRRTL representation instead of the Chisel representation on startup
     #[arg(long)]
-    pub fir: Option<bool>
+    pub fir: Option<bool>,
+
+    /// Comma-separated list of conversion passes to run, in order. Defaults to all passes
+    #[arg(long, value_delimiter = ',')]
+    pub passes: Option<Vec<String>>,
+
+    /// Comma-separated list of conversion passes that will not be run
+    #[arg(long, value_delimiter = ',')]
+    pub disable_passes: Option<Vec<String>>,
+
+    /// The DPDG of a passing run of the same criterion and representation, as written by `chiseltrace-cli trace`.
+    /// Enables the diff overlay, and its values are used as the expected values when ranking root causes
+    #[arg(long)]
+    pub passing_dpdg: Option<String>,
+
+    /// A CSV or JSON file of (signal, cycle, value) entries dumped by a golden model. Nodes whose values differ are highlighted
+    #[arg(long)]
+    pub golden_values: Option<String>,
+
+    /// Shows the values of each node's signal in this many cycles before and after the node in its tooltip
+    #[arg(long, value_name = "CYCLES")]
+    pub value_history: Option<u32>
 }
 
 impl Args {
@@ -68,9 +89,29 @@ impl Args {
             anyhow::bail!(errors::Error::ArgumentValidationError("Invalid VCD path".into()));
         }
 
-        let hgldd_path = Path::new(&self.hgldd_path);
-        if !(hgldd_path.exists() && hgldd_path.is_dir()) {
-            anyhow::bail!(errors::Error::ArgumentValidationError("Invalid HGLDD path".into()));
+        if let Some(hgldd_path) = &self.hgldd_path {
+            let hgldd_path = Path::new(hgldd_path);
+            if !(hgldd_path.exists() && hgldd_path.is_dir()) {
+                anyhow::bail!(errors::Error::ArgumentValidationError("Invalid HGLDD path".into()));
+            }
+        }
+
+        if let Some(passing_dpdg) = &self.passing_dpdg {
+            let passing_dpdg = Path::new(passing_dpdg);
+            if !(passing_dpdg.exists() && passing_dpdg.is_file()) {
+                anyhow::bail!(errors::Error::ArgumentValidationError("Invalid passing DPDG path".into()));
+            }
+        }
+
+        if let Some(golden_values) = &self.golden_values {
+            let golden_values = Path::new(golden_values);
+            if !(golden_values.exists() && golden_values.is_file()) {
+                anyhow::bail!(errors::Error::ArgumentValidationError("Invalid golden values path".into()));
+            }
+        }
+
+        if let Err(e) = PassManager::from_config(self.passes.as_deref(), self.disable_passes.as_deref().unwrap_or_default()) {
+            anyhow::bail!(errors::Error::ArgumentValidationError(e.to_string()));
         }
 
         Ok(self)
@@ -1,11 +1,48 @@
-use std::{collections::{HashMap, HashSet}, fs::{read_to_string, File}, io::BufReader, sync::{Arc, RwLock}, time::SystemTime};
+use std::{collections::{HashMap, HashSet}, sync::{Arc, RwLock}};
 
-use chiseltrace_rs::{conversion::{dpdg_make_exportable, pdg_convert_to_source}, graphbuilder::{GraphBuilder, GraphProcessingType}, pdg_spec::{ExportablePDG, ExportablePDGNode, PDGSpec}, sim_data_injection::TywavesInterface};
-use serde::Deserialize;
+use chiseltrace_rs::{conversion::{PassManager, DEFAULT_PASSES}, pdg_spec::{ExportablePDG, ExportablePDGNode}, pipeline::{build_trace, Trace}, query::build_lookup_tables};
+use serde::Serialize;
 use tauri::State;
 use anyhow::{anyhow, Result};
 
-use crate::{app_state::{AppState, GraphNodeHierarchy, HierarchicalGraph, ViewableGraph}, errors::map_err_to_string_async};
+use crate::{app_state::{map_firrtl_to_chisel, AppState, GraphNodeHierarchy, GraphRepresentation, HierarchicalGraph, ViewableGraph}, errors::{map_err_to_string, map_err_to_string_async}};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionPassConfig {
+    enabled: Vec<String>,
+    available: Vec<String>
+}
+
+/// Returns the conversion passes that are used to build the DPDG, and all passes that may be used.
+#[tauri::command]
+pub fn get_conversion_passes(state: State<'_, RwLock<AppState>>) -> Result<ConversionPassConfig, String> {
+    map_err_to_string(|| {
+        let state_guard = state.read().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(pdg_config) = &state_guard.pdg_config else {
+            anyhow::bail!("Uninitialized config!");
+        };
+        Ok(ConversionPassConfig {
+            enabled: pdg_config.conversion_passes.clone(),
+            available: DEFAULT_PASSES.iter().map(|p| p.to_string()).collect()
+        })
+    })
+}
+
+/// Sets the (ordered) list of conversion passes. The DPDG needs to be rebuilt using `make_dpdg` for this to take effect.
+#[tauri::command]
+pub fn set_conversion_passes(state: State<'_, RwLock<AppState>>, passes: Vec<String>) -> Result<(), String> {
+    map_err_to_string(|| {
+        // Reject unknown passes before storing them
+        PassManager::from_config(Some(&passes), &[])?;
+        let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(pdg_config) = &mut state_guard.pdg_config else {
+            anyhow::bail!("Uninitialized config!");
+        };
+        pdg_config.conversion_passes = passes;
+        Ok(())
+    })
+}
 
 #[tauri::command]
 pub async fn make_dpdg(state: State<'_, RwLock<AppState>>) -> Result<(), String> {
@@ -23,120 +60,33 @@ pub async fn make_dpdg(state: State<'_, RwLock<AppState>>) -> Result<(), String>
             };
 
             enable_grouping = pdg_config.group_nodes;
-            
-            // for _ in 0..100 {
-            let start_time = SystemTime::now();
-            let mut now = SystemTime::now();
-            let reader = BufReader::new(File::open(&pdg_config.pdg_path)?);
 
-            let mut deser = serde_json::Deserializer::from_reader(reader);
-            deser.disable_recursion_limit();
-            //serde_json::from_str::<PDGSpec>(buf.as_str())?;
-            let pdg_raw = PDGSpec::deserialize(&mut deser)?;
-            println!("Processing PDG with {} nodes and {} edges", pdg_raw.vertices.len(), pdg_raw.edges.len());
-            let sliced = pdg_raw;
-
-            println!("PDG read: {}", (now.elapsed().unwrap().as_nanos() as f64) / 1e6);
-            now = SystemTime::now();
-
-            println!("Read PDG from file");
-
-            // First do a static slice to try to reduce the amount of analyzed nodes
-            // let sliced = pdg_slice(pdg_raw, &pdg_config.criterion)?;
-
-            // Build the DPDG
-            let mut builder = GraphBuilder::new(&pdg_config.vcd_path, pdg_config.extra_scopes.clone(), sliced)?;
-            let processing_type = if pdg_config.data_only { GraphProcessingType::DataOnly } else {GraphProcessingType::Normal };
-            let dpdg = builder.process(&pdg_config.criterion, pdg_config.max_timesteps.map(|t| t as i64), processing_type)?;
-
-            println!("DPDG build: {}", (now.elapsed().unwrap().as_nanos() as f64) / 1e6);
-            now = SystemTime::now();
-            println!("DPDG build complete");
-
-            let dpdg = dpdg_make_exportable(dpdg);
-
-            println!("Exportable: {}", (now.elapsed().unwrap().as_nanos() as f64) / 1e6);
-            now = SystemTime::now();
-            println!("Made DPDG exportable");
+            let Trace { firrtl: dpdg, chisel: chisel_dpdg, source_files, timings } = build_trace(&pdg_config.trace_config())?;
+            for (stage, duration) in &timings {
+                println!("{}: {}", stage, duration.as_secs_f64() * 1e3);
+            }
+            println!("Total: {}", timings.iter().map(|(_, d)| d.as_secs_f64() * 1e3).sum::<f64>());
 
-            // Convert to source language
-            let mut converted_pdg = if !pdg_config.fir_repr {
-                 pdg_convert_to_source(dpdg, false, true)
+            // Both representations are kept, so the viewer can switch between them.
+            let firrtl_to_chisel = map_firrtl_to_chisel(&dpdg, &chisel_dpdg);
+            let (converted_pdg, other_dpdg, representation) = if pdg_config.fir_repr {
+                (dpdg, chisel_dpdg, GraphRepresentation::Firrtl)
             } else {
-                dpdg
+                (chisel_dpdg, dpdg, GraphRepresentation::Chisel)
             };
 
-            println!("Conversion: {}", (now.elapsed().unwrap().as_nanos() as f64) / 1e6);
-            now = SystemTime::now();
-            println!("Converted to source representation");
-            
             println!("DPDG has {} nodes and {} edges", converted_pdg.vertices.len(), converted_pdg.edges.len());
 
-            // Add simulation data
-            let tywaves = TywavesInterface::new(&pdg_config.hgldd_path, pdg_config.extra_scopes.clone(), &pdg_config.top_module)?;
-        
-            let tywaves_vcd_path = tywaves.vcd_rewrite(&pdg_config.vcd_path)?;
-            println!("VCD rewrite done");
-            tywaves.inject_sim_data(&mut converted_pdg, &tywaves_vcd_path)?;
-
-            println!("Tywaves: {}", (now.elapsed().unwrap().as_nanos() as f64) / 1e6);
-
-            for v in &mut converted_pdg.vertices {
-                v.timestamp += 1;
-            }
-
-            println!("Total: {}", (start_time.elapsed().unwrap().as_nanos() as f64) / 1e6);
-
-            //let converted_pdg = dpdg;
-
-            println!("Data injection done");
-
             let (node_hierarchy, node_hierarchy_lookup) = if pdg_config.group_nodes {
                 let (x, y) = build_node_hierarchy(&converted_pdg);
                 (Some(x), Some(y))
             } else { (None, None) };
 
             // Create maps to speed up the viewer
-            let mut time_to_nodes = HashMap::new();
-            for (idx, v) in converted_pdg.vertices.iter().enumerate() {
-                time_to_nodes.entry(v.timestamp).and_modify(|nodes: &mut Vec<usize>| nodes.push(idx)).or_insert(vec![idx]);
-            }
-
-            let mut dep_to_edges = HashMap::new();
-            for (idx, e) in converted_pdg.edges.iter().enumerate() {
-                dep_to_edges.entry(e.from).and_modify(|edges: &mut Vec<usize>| edges.push(idx)).or_insert(vec![idx]);
-            }
-
-            let mut prov_to_edges = HashMap::new();
-            for (idx, e) in converted_pdg.edges.iter().enumerate() {
-                prov_to_edges.entry(e.to).and_modify(|edges: &mut Vec<usize>| edges.push(idx)).or_insert(vec![idx]);
-            }
+            let (time_to_nodes, dep_to_edges, prov_to_edges) = build_lookup_tables(&converted_pdg);
 
             let n_timestamps = converted_pdg.vertices.iter().fold(0, |acc, x| acc.max(x.timestamp)) as u64;
 
-            // Find unique source files
-            let mut source_paths = HashSet::new();
-            for v in &converted_pdg.vertices {
-                source_paths.insert(v.file.clone());
-            }
-
-            // Just read them all to memory, then can't be that big
-            let mut source_files = HashMap::new();
-            for p in source_paths {
-                // This is a hacky fix that appends the root symbol / if the file is in the home directory.
-                // For some reason, the exported PDG does not contain this symbol.
-                // This will not work on windows.
-                let read_path = if p.starts_with("home") {
-                    &("/".to_string() + &p)
-                } else {
-                    &p
-                };
-
-                if let Ok(contents) = read_to_string(&read_path) {
-                    source_files.insert(p, contents.lines().map(String::from).collect());
-                }
-            }
-
             let viewable_graph = ViewableGraph {
                 dpdg: converted_pdg.clone(),
                 shown_ids: (0..converted_pdg.vertices.len()).collect(),
@@ -148,7 +98,12 @@ pub async fn make_dpdg(state: State<'_, RwLock<AppState>>) -> Result<(), String>
                 should_group_nodes: pdg_config.group_nodes,
                 node_hierarchy,
                 node_hierarchy_lookup,
-                current_hier_dpdg: None
+                current_hier_dpdg: None,
+                representation,
+                other_dpdg,
+                firrtl_to_chisel,
+                head: None,
+                highlights: HashMap::new()
             };
 
             let mut state_guard = state.write().map_err(|_| anyhow::anyhow!("RwLock poisoned"))?;
@@ -162,6 +117,20 @@ pub async fn make_dpdg(state: State<'_, RwLock<AppState>>) -> Result<(), String>
     }).await
 }
  
+/// Recomputes everything that is derived from the shown DPDG, after it has been changed or replaced.
+/// The hierarchical graph itself has to be rebuilt with `rebuild_hier_graph` afterwards.
+pub fn reindex_graph(graph: &mut ViewableGraph) {
+    (graph.time_to_nodes, graph.dep_to_edges, graph.prov_to_edges) = build_lookup_tables(&graph.dpdg);
+    graph.n_timestamps = graph.dpdg.vertices.iter().fold(0, |acc, x| acc.max(x.timestamp)) as u64;
+
+    // The hierarchy refers to node IDs, so it has to be rebuilt as well
+    if graph.should_group_nodes {
+        let (node_hierarchy, node_hierarchy_lookup) = build_node_hierarchy(&graph.dpdg);
+        graph.node_hierarchy = Some(node_hierarchy);
+        graph.node_hierarchy_lookup = Some(node_hierarchy_lookup);
+    }
+}
+
 /// Rebuilds the DPDG that is currently being displayed based on the hierarchical levels that are expanded.
 pub fn rebuild_hier_graph(state: &State<'_, RwLock<AppState>>) -> Result<()> {
     let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
@@ -290,7 +259,7 @@ fn get_highest_hier_node(hierarchy: &Arc<RwLock<GraphNodeHierarchy>>) -> Option<
 }
 
 fn create_hier_pdg_node(name: String, timestamp: i64, module_path: Vec<String>) -> ExportablePDGNode {
-    ExportablePDGNode { file: "".into(), line: 0, char: 0, name, kind: chiseltrace_rs::pdg_spec::PDGSpecNodeKind::Definition, clocked: false, module_path, related_signal: None, sim_data: None, timestamp, is_chisel_assignment: false }
+    ExportablePDGNode { file: "".into(), line: 0, char: 0, end_char: None, name, kind: chiseltrace_rs::pdg_spec::PDGSpecNodeKind::Definition, clocked: false, module_path, related_signal: None, assigns_to: None, predicate: None, provenance: vec![], sim_data: None, sim_history: None, golden_mismatch: None, timestamp, is_chisel_assignment: false }
 }
 
 /// Builds a node hierarchy by first creating the hierarchy, then adding the nodes and making a reverse mapping
@@ -1,12 +1,13 @@
-use std::{collections::HashSet, process::Command, sync::RwLock};
+use std::{collections::HashSet, fs::File, io::BufReader, path::{Path, PathBuf}, process::Command, sync::RwLock};
 
 use anyhow::anyhow;
 use itertools::Itertools;
-use chiseltrace_rs::pdg_spec::{ExportablePDG, PDGSpecEdgeKind, PDGSpecNodeKind};
+use chiseltrace_rs::{conversion::expand_node, diff::{diff_dpdgs, DpdgDiff}, export::{write_dot, DotOptions, EdgeColour, NodeColour, NodeShape}, golden::GoldenValues, pdg_spec::{ExportablePDG, ExportablePDGEdge, PDGSpecEdgeKind, PDGSpecNodeKind}, query, root_cause::{find_criterion, rank_root_causes, RootCauseCandidate}, translation::{interpret_node_value, interpret_tywaves_value, node_label, TranslationResult}, value_format::{signal_key, FormatOverrides, ValueFormat}, x_propagation::{has_undefined_bits, trace_x_origins, XOrigin}};
+use serde::Deserialize;
 use serde::Serialize;
 use tauri::State;
 
-use crate::{app_state::{AppState, ViewableGraph}, errors::map_err_to_string, graph_building::rebuild_hier_graph, translation::{interpret_tywaves_value, TranslationStrategy}};
+use crate::{app_state::{map_firrtl_to_chisel, AppState, GraphRepresentation, ViewableGraph}, errors::map_err_to_string, graph_building::{rebuild_hier_graph, reindex_graph}};
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -19,6 +20,9 @@ struct ViewerGraph {
 #[serde(rename_all = "camelCase")]
 struct ViewerNode {
     id: u64,
+    /// The DPDG vertex that the node shows. This differs from the ID for long-distance pseudo nodes, and is not set for
+    /// collapsed modules.
+    vertex: Option<usize>,
     label: String,
     group: String, // The timeslot group
     module_path: Vec<String>,
@@ -30,7 +34,13 @@ struct ViewerNode {
     incoming: Vec<ViewerSignal>,
     outgoing: Vec<ViewerSignal>,
     file: String,
-    line: u32
+    line: u32,
+    /// The signal of the node as it is named in the display format overrides
+    signal: Option<String>,
+    /// The source language type of the value of the node, such as `UInt<8>`
+    value_type: Option<String>,
+    /// Why the node is highlighted, if it is
+    highlight: Option<String>
 }
 
 #[derive(Debug, Clone, Serialize, Hash, PartialEq, Eq)]
@@ -38,65 +48,24 @@ struct ViewerNode {
 struct ViewerSignal {
     name: String,
     value: String,
+    /// The fields of bundle and vec values, shown as a tree
+    fields: Vec<ViewerValueField>,
     connection_type: String
 }
 
-#[derive(Debug)]
-enum NodeColour {
-    Yellow,
-    Green,
-    Blue,
-    Red
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "lowercase")]
-enum NodeShape {
-    Ellipse,
-    Box,
-    Diamond
-}
-
-impl From<PDGSpecNodeKind> for NodeColour {
-    fn from(value: PDGSpecNodeKind) -> Self {
-        match value {
-            PDGSpecNodeKind::Connection => NodeColour::Blue,
-            PDGSpecNodeKind::ControlFlow => NodeColour::Red,
-            PDGSpecNodeKind::IO => NodeColour::Green,
-            PDGSpecNodeKind::DataDefinition => NodeColour::Blue,
-            PDGSpecNodeKind::Definition => NodeColour::Yellow
-        }
-    }
-}
-
-impl NodeColour {
-    fn to_hex(&self) -> String {
-        match self {
-            &NodeColour::Blue => "#97C2FC",
-            &NodeColour::Red => "#FB7E81",
-            &NodeColour::Green => "#7BE141",
-            &NodeColour::Yellow => "#FFFF00"
-        }.into()
-    }
-}
-
-
-impl Serialize for NodeColour {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(&self.to_hex())
-    }
+#[derive(Debug, Clone, Serialize, Hash, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct ViewerValueField {
+    name: String,
+    value: String,
+    fields: Vec<ViewerValueField>
 }
 
-impl From<PDGSpecNodeKind> for NodeShape {
-    fn from(value: PDGSpecNodeKind) -> Self {
-        match value {
-            PDGSpecNodeKind::Connection => NodeShape::Ellipse,
-            PDGSpecNodeKind::ControlFlow => NodeShape::Diamond,
-            _ => NodeShape::Box
-        }
+impl ViewerValueField {
+    fn from_fields(value: &TranslationResult) -> Vec<ViewerValueField> {
+        value.fields.iter()
+            .map(|(name, f)| ViewerValueField { name: name.clone(), value: f.value.clone(), fields: Self::from_fields(f) })
+            .collect()
     }
 }
 
@@ -112,46 +81,8 @@ struct ViewerEdge {
     // Same here, add colours, simulation values etc.
 }
 
-#[derive(Debug)]
-enum EdgeColour {
-    Blue,
-    Red,
-    Purple
-}
-
-impl From<PDGSpecEdgeKind> for EdgeColour {
-    fn from(value: PDGSpecEdgeKind) -> Self {
-        match value {
-            PDGSpecEdgeKind::Data => EdgeColour::Blue,
-            PDGSpecEdgeKind::Conditional => EdgeColour::Red,
-            PDGSpecEdgeKind::Index => EdgeColour::Purple,
-            PDGSpecEdgeKind::Declaration => EdgeColour::Blue,
-        }
-    }
-}
-
-impl EdgeColour {
-    fn to_hex(&self) -> String {
-        match self {
-            &EdgeColour::Blue => "#97C2FC".into(),
-            &EdgeColour::Red => "#FB7E81".into(),
-            &EdgeColour::Purple => "#bc2dcc".into(),
-        }
-    }
-}
-
-
-impl Serialize for EdgeColour {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(&self.to_hex())
-    }
-}
-
 /// Get the signals that will be displayed in the hover tooltip
-fn get_viewer_signals(dpdg: &ExportablePDG, edges: &Vec<usize>, incoming: bool) -> Vec<ViewerSignal> {
+fn get_viewer_signals(dpdg: &ExportablePDG, edges: &Vec<usize>, incoming: bool, formats: &FormatOverrides) -> Vec<ViewerSignal> {
     edges.iter().map(|e| {
         let edge = &dpdg.edges[*e];
         let destination =  if incoming {
@@ -166,11 +97,9 @@ fn get_viewer_signals(dpdg: &ExportablePDG, edges: &Vec<usize>, incoming: bool)
                 format!("{} [{}]", signal.signal_path, signal.field_path)
             }
         } else { "".into() };
-        let value = destination.sim_data.as_ref().map(|d|  {
-            let translated = interpret_tywaves_value(&d, TranslationStrategy::Auto);
-            // format!("{} {}", translated.tpe.unwrap_or("".into()), translated.value)
-            translated.value
-        }).unwrap_or("".into());
+        let translated = interpret_node_value(destination, formats);
+        let value = translated.as_ref().map(|t| t.value.clone()).unwrap_or("".into());
+        let fields = translated.as_ref().map(ViewerValueField::from_fields).unwrap_or_default();
         let connection_type = match edge.kind {
             PDGSpecEdgeKind::Conditional => "controlflow",
             PDGSpecEdgeKind::Data => "data",
@@ -180,6 +109,7 @@ fn get_viewer_signals(dpdg: &ExportablePDG, edges: &Vec<usize>, incoming: bool)
         ViewerSignal {
             name,
             value,
+            fields,
             connection_type
         }
     }).unique().collect()
@@ -196,6 +126,90 @@ pub fn get_n_timeslots(state: State<'_, RwLock<AppState>>) -> Result<u64, String
     })
 }
 
+/// What a display format override applies to
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatTarget {
+    /// A signal, or a field of one, such as `io.out.bits`
+    Signal,
+    /// All signals of a type, such as `UInt<32>` or `UInt`
+    Type
+}
+
+/// Sets the format in which the values of a signal or type are displayed. Without a format, the override is removed.
+#[tauri::command]
+pub fn set_value_format(state: State<'_, RwLock<AppState>>, target: FormatTarget, name: String, format: Option<ValueFormat>) -> Result<(), String> {
+    map_err_to_string(|| -> anyhow::Result<()> {
+        let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let overrides = match target {
+            FormatTarget::Signal => &mut state_guard.value_formats.signals,
+            FormatTarget::Type => &mut state_guard.value_formats.types
+        };
+        if let Some(format) = format {
+            overrides.insert(name, format);
+        } else {
+            overrides.remove(&name);
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn get_value_formats(state: State<'_, RwLock<AppState>>) -> Result<FormatOverrides, String> {
+    map_err_to_string(|| -> anyhow::Result<FormatOverrides> {
+        let state_guard = state.read().map_err(|_| anyhow!("RwLock poisoned"))?;
+        Ok(state_guard.value_formats.clone())
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MiniWaveform {
+    signal: String,
+    /// Whether the value of the node differs from the one in the cycle before it
+    changed: bool,
+    samples: Vec<WaveformSample>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WaveformSample {
+    timestamp: i64,
+    value: String,
+    /// Whether the value differs from the one in the previous sample
+    changed: bool
+}
+
+/// Returns the values of the signal of a DPDG vertex in the cycles around it, to show whether a value was a transition
+/// or had been stuck for a while. Returns nothing if the vertex has no signal, or the value history was not enabled.
+#[tauri::command]
+pub fn get_value_history(state: State<'_, RwLock<AppState>>, vertex: usize) -> Result<Option<MiniWaveform>, String> {
+    map_err_to_string(|| {
+        let state_guard = state.read().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(graph) = &state_guard.graph else {
+            anyhow::bail!("Uninitialized graph!");
+        };
+        let node = graph.dpdg.vertices.get(vertex).ok_or(anyhow!("Node {} does not exist", vertex))?;
+        let (Some(signal), Some(history)) = (&node.related_signal, &node.sim_history) else {
+            return Ok(None);
+        };
+
+        let key = signal_key(signal);
+        let mut previous_bits = None;
+        let samples = history.window.iter().map(|(timestamp, value)| {
+            let changed = previous_bits.is_some_and(|bits| bits != &value.bits);
+            previous_bits = Some(&value.bits);
+            WaveformSample {
+                timestamp: *timestamp,
+                value: interpret_tywaves_value(value, Some(&key), &state_guard.value_formats).value,
+                changed
+            }
+        }).collect();
+
+        Ok(Some(MiniWaveform { signal: key, changed: history.changed, samples }))
+    })
+}
+
 /// A command that toggles the expanded state of a group of nodes
 #[tauri::command]
 pub fn toggle_module(state: State<'_, RwLock<AppState>>, module_path: Vec<String>, timestamp: i64) -> Result<(), String> {
@@ -234,6 +248,11 @@ pub fn toggle_module(state: State<'_, RwLock<AppState>>, module_path: Vec<String
     })
 }
 
+/// The IDs of all nodes that the given node (transitively) depends on, including the node itself.
+fn reachable_from(graph: &ViewableGraph, id: usize) -> HashSet<usize> {
+    query::reachable_from(&graph.dpdg, &graph.dep_to_edges, id)
+}
+
 /// Sets the new graph head by calculating reachability and setting other nodes to hidden
 #[tauri::command]
 pub fn set_new_head(state: State<'_, RwLock<AppState>>, id: usize) -> Result<(), String> {
@@ -248,27 +267,106 @@ pub fn set_new_head(state: State<'_, RwLock<AppState>>, id: usize) -> Result<(),
             return Ok(());
         }
 
-        let mut nodes_reached = HashSet::new();
+        graph.shown_ids = reachable_from(graph, id);
+        graph.head = Some(id);
 
-        let mut stack = vec![id];
-        while let Some(node_idx) = stack.pop() {
-            nodes_reached.insert(node_idx);
-            if let Some(edges) = graph.dep_to_edges.get(&(node_idx as u32)) {
-                for edge_idx in edges {
-                    let edge = &graph.dpdg.edges[*edge_idx];
-                    if !nodes_reached.contains(&(edge.to as usize)) {
-                        stack.push(edge.to as usize);
-                    }
-                }
+        Ok(())
+    })
+}
+
+/// Replaces a Chisel node by the FIRRTL statements it was converted from. The other nodes keep their IDs.
+#[tauri::command]
+pub fn expand_firrtl_node(state: State<'_, RwLock<AppState>>, id: usize) -> Result<(), String> {
+    map_err_to_string(|| {
+        let should_group_nodes = {
+            let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+            let Some(graph) = &mut state_guard.graph else {
+                anyhow::bail!("Uninitialized graph!");
+            };
+            if graph.representation != GraphRepresentation::Chisel {
+                anyhow::bail!("The graph is in FIRRTL representation, there is nothing to expand");
             }
-        }
-        
-        graph.shown_ids = nodes_reached;
 
+            // Some IDs may not correspond to a real node: ignore those
+            if id >= graph.dpdg.vertices.len() {
+                return Ok(());
+            }
+
+            let new_ids = expand_node(&mut graph.dpdg, &graph.other_dpdg, id as u32)?;
+            if graph.shown_ids.contains(&id) {
+                graph.shown_ids.extend(new_ids.iter().map(|i| *i as usize));
+            }
+            graph.firrtl_to_chisel = map_firrtl_to_chisel(&graph.other_dpdg, &graph.dpdg);
+            reindex_graph(graph);
+            graph.should_group_nodes
+        };
+        if should_group_nodes {
+            rebuild_hier_graph(&state)?;
+        }
         Ok(())
     })
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepresentationSwitch {
+    selected: Option<usize>,
+    range_begin: u64,
+    range_end: u64
+}
+
+/// Returns the representation that the viewer shows
+#[tauri::command]
+pub fn get_representation(state: State<'_, RwLock<AppState>>) -> Result<GraphRepresentation, String> {
+    map_err_to_string(|| {
+        let state_guard = state.read().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(graph) = &state_guard.graph else {
+            anyhow::bail!("Uninitialized graph!");
+        };
+        Ok(graph.representation)
+    })
+}
+
+/// Switches the viewer between the FIRRTL and the Chisel representation. The head of the graph is carried over, and the
+/// selected node is mapped to its counterpart. Returns the new selection and time window.
+#[tauri::command]
+pub fn switch_representation(state: State<'_, RwLock<AppState>>, representation: GraphRepresentation, range_begin: u64, range_end: u64, selected: Option<usize>) -> Result<RepresentationSwitch, String> {
+    map_err_to_string(|| {
+        let (switch, should_group_nodes) = {
+            let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+            let Some(graph) = &mut state_guard.graph else {
+                anyhow::bail!("Uninitialized graph!");
+            };
+            if graph.representation == representation {
+                return Ok(RepresentationSwitch { selected, range_begin, range_end });
+            }
+
+            let selected = selected.and_then(|id| graph.corresponding_node(id));
+            let head = graph.head.and_then(|id| graph.corresponding_node(id));
+
+            std::mem::swap(&mut graph.dpdg, &mut graph.other_dpdg);
+            graph.representation = representation;
+            // The highlights refer to the IDs of the other representation
+            graph.highlights.clear();
+            graph.current_hier_dpdg = None;
+            reindex_graph(graph);
+
+            // Both representations have the same timestamps, so only the end of the window may have to be clamped
+            graph.head = head;
+            graph.shown_ids = match head {
+                Some(head) => reachable_from(graph, head),
+                None => (0..graph.dpdg.vertices.len()).collect()
+            };
+            let range_end = range_end.min(graph.n_timestamps);
+            (RepresentationSwitch { selected, range_begin: range_begin.min(range_end), range_end }, graph.should_group_nodes)
+        };
+        if should_group_nodes {
+            rebuild_hier_graph(&state)?;
+        }
+        Ok(switch)
+    })
+}
+
 /// Resets the graph head
 #[tauri::command]
 pub fn reset_head(state: State<'_, RwLock<AppState>>) -> Result<(), String> {
@@ -279,6 +377,7 @@ pub fn reset_head(state: State<'_, RwLock<AppState>>) -> Result<(), String> {
         };
         
         graph.shown_ids = (0..graph.dpdg.vertices.len()).collect();
+        graph.head = None;
 
         Ok(())
     })
@@ -314,6 +413,7 @@ pub fn get_partial_graph(state: State<'_, RwLock<AppState>>, range_begin: u64, r
         let Some(graph) = &state_guard.graph else {
             anyhow::bail!("Uninitialized graph!");
         };
+        let formats = &state_guard.value_formats;
 
         if !graph.should_group_nodes { // Regular 
             let mut viewer_graph = ViewerGraph { vertices: vec![], edges: vec![] };
@@ -328,11 +428,12 @@ pub fn get_partial_graph(state: State<'_, RwLock<AppState>>, range_begin: u64, r
                     let node = &graph.dpdg.vertices[*idx];
                     let edges = graph.dep_to_edges.get(&(*idx as u32));
                     let group = format!("t{}", graph.n_timestamps - timestamp);
-                    let incoming = edges.map_or(vec![], |edges| get_viewer_signals(&graph.dpdg, edges, true));
-                    let outgoing = graph.prov_to_edges.get(&(*idx as u32)).map_or(vec![], |edges| get_viewer_signals(&graph.dpdg, edges, true));
+                    let incoming = edges.map_or(vec![], |edges| get_viewer_signals(&graph.dpdg, edges, true, formats));
+                    let outgoing = graph.prov_to_edges.get(&(*idx as u32)).map_or(vec![], |edges| get_viewer_signals(&graph.dpdg, edges, true, formats));
                     viewer_graph.vertices.push(ViewerNode {
                         id: *idx as u64,
-                        label: node.name.clone(),
+                        vertex: Some(*idx),
+                        label: node_label(node, formats),
                         group: group.clone(),
                         module_path: node.module_path.clone(),
                         timestamp,
@@ -343,25 +444,28 @@ pub fn get_partial_graph(state: State<'_, RwLock<AppState>>, range_begin: u64, r
                         incoming,
                         outgoing,
                         file: node.file.clone(),
-                        line: node.line
+                        line: node.line,
+                        signal: node.related_signal.as_ref().map(signal_key),
+                        value_type: node.sim_data.as_ref().and_then(|v| v.type_name.clone()),
+                        highlight: graph.highlight(*idx)
                     });
                     if let Some(edges) = edges {
                         for edge in edges {
                             let edge = &graph.dpdg.edges[*edge];
                             let destination = &graph.dpdg.vertices[edge.to as usize];
-                            let label = if let Some(d) = &destination.sim_data {
-                                let translated = interpret_tywaves_value(d, TranslationStrategy::Auto);
-                                format!("{} {}", translated.tpe.unwrap_or("".into()), translated.value)
-                            } else { "".into() };
+                            let label = interpret_node_value(destination, formats)
+                                .map(|t| t.label())
+                                .unwrap_or_default();
                             if node.timestamp.abs_diff(destination.timestamp) > 3 {
                                 let edges = graph.dep_to_edges.get(&edge.to);
-                                let incoming = edges.map_or(vec![], |edges| get_viewer_signals(&graph.dpdg, edges, true));
-                                let outgoing = graph.prov_to_edges.get(&edge.to).map_or(vec![], |edges| get_viewer_signals(&graph.dpdg, edges, true));
+                                let incoming = edges.map_or(vec![], |edges| get_viewer_signals(&graph.dpdg, edges, true, formats));
+                                let outgoing = graph.prov_to_edges.get(&edge.to).map_or(vec![], |edges| get_viewer_signals(&graph.dpdg, edges, true, formats));
                                 // If an edge goes to a node that is more than 3 timesteps away, instead add it as a long distance relation
                                 // It is important to generate a unique ID for these pseudo-nodes, because they MUST be unique in the graph
                                 viewer_graph.vertices.push(ViewerNode {
                                     id: edge.to as u64 + graph.dpdg.vertices.len() as u64 + edge.from as u64,
-                                    label: destination.name.clone(),
+                                    vertex: Some(edge.to as usize),
+                                    label: node_label(destination, formats),
                                     group: group.clone(),
                                     module_path: destination.module_path.clone(),
                                     timestamp,
@@ -372,7 +476,10 @@ pub fn get_partial_graph(state: State<'_, RwLock<AppState>>, range_begin: u64, r
                                     incoming,
                                     outgoing,
                                     file: node.file.clone(),
-                                    line: node.line
+                                    line: node.line,
+                                    signal: destination.related_signal.as_ref().map(signal_key),
+                                    value_type: destination.sim_data.as_ref().and_then(|v| v.type_name.clone()),
+                                    highlight: graph.highlight(edge.to as usize)
                                 });
                                 viewer_graph.edges.push(ViewerEdge {
                                     from: edge.from as u64,
@@ -428,11 +535,12 @@ pub fn get_partial_graph(state: State<'_, RwLock<AppState>>, range_begin: u64, r
                     }
                     let edges = hier_graph.dep_to_edges.get(&(*idx as u32));
                     let group = format!("t{}", graph.n_timestamps - timestamp);
-                    let incoming = edges.map_or(vec![], |edges| get_viewer_signals(&hier_graph.dpdg, edges, true));
-                    let outgoing = hier_graph.prov_to_edges.get(&(*idx as u32)).map_or(vec![], |edges| get_viewer_signals(&hier_graph.dpdg, edges, true));
+                    let incoming = edges.map_or(vec![], |edges| get_viewer_signals(&hier_graph.dpdg, edges, true, formats));
+                    let outgoing = hier_graph.prov_to_edges.get(&(*idx as u32)).map_or(vec![], |edges| get_viewer_signals(&hier_graph.dpdg, edges, true, formats));
                     viewer_graph.vertices.push(ViewerNode {
                         id: hier_graph.original_ids[*idx] as u64,
-                        label: node.name.clone(),
+                        vertex: (!hier_graph.group_ids.contains_key(idx)).then_some(hier_graph.original_ids[*idx]),
+                        label: node_label(node, formats),
                         group: group.clone(),
                         module_path: node.module_path.clone(),
                         timestamp,
@@ -443,27 +551,30 @@ pub fn get_partial_graph(state: State<'_, RwLock<AppState>>, range_begin: u64, r
                         incoming,
                         outgoing,
                         file: node.file.clone(),
-                        line: node.line
+                        line: node.line,
+                        signal: node.related_signal.as_ref().map(signal_key),
+                        value_type: node.sim_data.as_ref().and_then(|v| v.type_name.clone()),
+                        highlight: graph.highlight(hier_graph.original_ids[*idx])
                     });
                     if let Some(edges) = edges {
                         for edge in edges {
                             let edge = &hier_graph.dpdg.edges[*edge];
                             let destination = &hier_graph.dpdg.vertices[edge.to as usize];
-                            let label = if let Some(d) = &destination.sim_data {
-                                let translated = interpret_tywaves_value(d, TranslationStrategy::Auto);
-                                format!("{} {}", translated.tpe.unwrap_or("".into()), translated.value)
-                            } else { "".into() };
+                            let label = interpret_node_value(destination, formats)
+                                .map(|t| t.label())
+                                .unwrap_or_default();
                             if node.timestamp.abs_diff(destination.timestamp) > 3 {
                                 let edges = hier_graph.dep_to_edges.get(&edge.to);
-                                let incoming = edges.map_or(vec![], |edges| get_viewer_signals(&hier_graph.dpdg, edges, true));
-                                let outgoing = hier_graph.prov_to_edges.get(&edge.to).map_or(vec![], |edges| get_viewer_signals(&hier_graph.dpdg, edges, true));
+                                let incoming = edges.map_or(vec![], |edges| get_viewer_signals(&hier_graph.dpdg, edges, true, formats));
+                                let outgoing = hier_graph.prov_to_edges.get(&edge.to).map_or(vec![], |edges| get_viewer_signals(&hier_graph.dpdg, edges, true, formats));
                                 // If an edge goes to a node that is more than 3 timesteps away, instead add it as a long distance relation
                                 // It is important to generate a unique ID for these pseudo-nodes, because they MUST be unique in the graph
                                 let node_id = (hier_graph.original_ids[edge.to as usize] << 32) as u64 + 10 * graph.dpdg.vertices.len() as u64 + hier_graph.original_ids[edge.from as usize] as u64;
                                 viewer_graph.vertices.push(ViewerNode {
                                     // TODO: replace the 10x with an actual fix. This just shifts the duplicate ID problem elsewhere.
                                     id: node_id,
-                                    label: destination.name.clone(),
+                                    vertex: (!hier_graph.group_ids.contains_key(&(edge.to as usize))).then_some(hier_graph.original_ids[edge.to as usize]),
+                                    label: node_label(destination, formats),
                                     group: group.clone(),
                                     module_path: destination.module_path.clone(),
                                     timestamp,
@@ -474,7 +585,10 @@ pub fn get_partial_graph(state: State<'_, RwLock<AppState>>, range_begin: u64, r
                                     incoming,
                                     outgoing,
                                     file: node.file.clone(),
-                                    line: node.line
+                                    line: node.line,
+                                    signal: destination.related_signal.as_ref().map(signal_key),
+                                    value_type: destination.sim_data.as_ref().and_then(|v| v.type_name.clone()),
+                                    highlight: graph.highlight(hier_graph.original_ids[edge.to as usize])
                                 });
                                 viewer_graph.edges.push(ViewerEdge {
                                     from: hier_graph.original_ids[edge.from as usize] as u64,
@@ -502,3 +616,145 @@ pub fn get_partial_graph(state: State<'_, RwLock<AppState>>, range_begin: u64, r
         }
     })
 }
+
+/// Highlights the nodes that most likely caused the wrong value of the head of the graph, or of the criterion if there is
+/// no head. The values of the DPDG at `golden_path`, or else of the passing run, are taken as the expected values.
+/// Returns the ranking.
+#[tauri::command]
+pub fn highlight_root_causes(state: State<'_, RwLock<AppState>>, golden_path: Option<String>, limit: usize) -> Result<Vec<RootCauseCandidate>, String> {
+    map_err_to_string(|| {
+        let golden_path = golden_path.map(PathBuf::from).or_else(|| passing_dpdg_path(&state));
+        let golden = match golden_path {
+            Some(path) => Some(GoldenValues::from_pdg(&read_dpdg(&path)?)),
+            None => None
+        };
+
+        let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(graph) = &mut state_guard.graph else {
+            anyhow::bail!("Uninitialized graph!");
+        };
+        let Some(criterion) = graph.head.or_else(|| find_criterion(&graph.dpdg)) else {
+            anyhow::bail!("The graph is empty");
+        };
+
+        let mut candidates = rank_root_causes(&graph.dpdg, criterion, golden.as_ref());
+        candidates.truncate(limit);
+        graph.highlights = candidates.iter()
+            .enumerate()
+            .map(|(rank, c)| (c.node, format!("Root cause #{}: {}", rank + 1, c.reasons.iter().join(", "))))
+            .collect();
+        Ok(candidates)
+    })
+}
+
+/// Highlights where the shown graph diverges from the DPDG of the passing run. Activations that only happened in the
+/// passing run cannot be shown, but are part of the returned diff.
+#[tauri::command]
+pub fn show_diff_overlay(state: State<'_, RwLock<AppState>>) -> Result<DpdgDiff, String> {
+    map_err_to_string(|| {
+        let Some(path) = passing_dpdg_path(&state) else {
+            anyhow::bail!("No passing DPDG was given, start the viewer with --passing-dpdg");
+        };
+        let passing = read_dpdg(&path)?;
+
+        let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(graph) = &mut state_guard.graph else {
+            anyhow::bail!("Uninitialized graph!");
+        };
+
+        let diff = diff_dpdgs(&graph.dpdg, &passing);
+        graph.highlights.clear();
+        for (i, (idx, d)) in diff.divergences.iter().filter_map(|d| Some((d.failing?, d))).enumerate() {
+            let prefix = if i == 0 { "Earliest divergence" } else { "Diverges" };
+            graph.highlights.entry(idx).or_insert_with(|| format!("{} from the passing run: {}", prefix, d.kind));
+        }
+        Ok(diff)
+    })
+}
+
+fn passing_dpdg_path(state: &State<'_, RwLock<AppState>>) -> Option<PathBuf> {
+    let state_guard = state.read().ok()?;
+    state_guard.pdg_config.as_ref()?.passing_dpdg.clone()
+}
+
+/// Reads a DPDG as written by the ChiselTrace CLI
+fn read_dpdg(path: &Path) -> anyhow::Result<ExportablePDG> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+/// Highlights the statements where the undefined value of a node entered the design
+#[tauri::command]
+pub fn highlight_x_origins(state: State<'_, RwLock<AppState>>, id: usize) -> Result<Vec<XOrigin>, String> {
+    map_err_to_string(|| {
+        let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(graph) = &mut state_guard.graph else {
+            anyhow::bail!("Uninitialized graph!");
+        };
+
+        // Some IDs may not correspond to a real node: ignore those
+        if id >= graph.dpdg.vertices.len() {
+            return Ok(vec![]);
+        }
+        if !graph.dpdg.vertices[id].sim_data.as_ref().is_some_and(has_undefined_bits) {
+            anyhow::bail!("The value of the node is not undefined");
+        }
+
+        let origins = trace_x_origins(&graph.dpdg, id);
+        graph.highlights = origins.iter()
+            .map(|o| (o.node, format!("Origin of the undefined value: {}", o.kind)))
+            .collect();
+        Ok(origins)
+    })
+}
+
+/// Removes the highlights of the last analysis. Mismatches with the golden reference stay highlighted.
+#[tauri::command]
+pub fn clear_highlights(state: State<'_, RwLock<AppState>>) -> Result<(), String> {
+    map_err_to_string(|| {
+        let mut state_guard = state.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(graph) = &mut state_guard.graph else {
+            anyhow::bail!("Uninitialized graph!");
+        };
+        graph.highlights.clear();
+        Ok(())
+    })
+}
+
+/// Writes the graph that is currently shown to a Graphviz DOT file, with the display formats of the viewer
+#[tauri::command]
+pub fn export_dot(state: State<'_, RwLock<AppState>>, path: String, cluster_modules: bool) -> Result<(), String> {
+    map_err_to_string(|| {
+        let state_guard = state.read().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let Some(graph) = &state_guard.graph else {
+            anyhow::bail!("Uninitialized graph!");
+        };
+        let options = DotOptions { cluster_modules, formats: state_guard.value_formats.clone() };
+        write_dot(&shown_graph(graph)?, &options, path)
+    })
+}
+
+/// The part of the DPDG that is shown in the viewer, with the collapsed modules if nodes are grouped
+fn shown_graph(graph: &ViewableGraph) -> anyhow::Result<ExportablePDG> {
+    let (pdg, shown) = if graph.should_group_nodes {
+        let Some(hier_graph) = &graph.current_hier_dpdg else {
+            anyhow::bail!("Hierarchical graph not initialized!");
+        };
+        let shown = hier_graph.dpdg.vertices.iter().enumerate()
+            .map(|(idx, v)| graph.shown_ids.contains(&hier_graph.original_ids[idx]) || v.kind == PDGSpecNodeKind::Definition)
+            .collect::<Vec<_>>();
+        (&hier_graph.dpdg, shown)
+    } else {
+        (&graph.dpdg, (0..graph.dpdg.vertices.len()).map(|idx| graph.shown_ids.contains(&idx)).collect())
+    };
+
+    let mut new_index = vec![None; pdg.vertices.len()];
+    let mut vertices = vec![];
+    for (idx, v) in pdg.vertices.iter().enumerate().filter(|(idx, _)| shown[*idx]) {
+        new_index[idx] = Some(vertices.len() as u32);
+        vertices.push(v.clone());
+    }
+    let edges = pdg.edges.iter()
+        .filter_map(|e| Some(ExportablePDGEdge { from: new_index[e.from as usize]?, to: new_index[e.to as usize]?, ..e.clone() }))
+        .collect();
+    Ok(ExportablePDG { vertices, edges })
+}
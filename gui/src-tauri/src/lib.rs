@@ -1,18 +1,18 @@
-use std::sync::RwLock;
+use std::{path::PathBuf, sync::RwLock};
 
 use clap::Parser;
 use anyhow::Result;
 
 use app_state::{AppState, PDGConfig};
-use graph_building::make_dpdg;
-use graph_interaction::{get_n_timeslots, get_partial_graph, toggle_module, set_new_head, reset_head, open_vs_code};
+use chiseltrace_rs::conversion::PassManager;
+use graph_building::{make_dpdg, get_conversion_passes, set_conversion_passes};
+use graph_interaction::{get_n_timeslots, get_partial_graph, toggle_module, set_new_head, reset_head, open_vs_code, expand_firrtl_node, get_representation, switch_representation, set_value_format, get_value_formats, get_value_history, export_dot, highlight_root_causes, show_diff_overlay, highlight_x_origins, clear_highlights};
 
 mod argument_parsing;
 mod errors;
 mod graph_building;
 mod app_state;
 mod graph_interaction;
-mod translation;
 
 #[tauri::command]
 fn get_initial_route() -> String {
@@ -22,23 +22,33 @@ fn get_initial_route() -> String {
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() -> Result<()> {
     let args = argument_parsing::Args::parse().validate()?;
+    let conversion_passes = PassManager::from_config(args.passes.as_deref(), args.disable_passes.as_deref().unwrap_or_default())?
+        .pass_names()
+        .into_iter()
+        .map(String::from)
+        .collect();
     let mut state = AppState::new();
     state.pdg_config = Some(PDGConfig { criterion: args.slice_criterion,
         pdg_path: args.pdg_path.into(),
         vcd_path: args.vcd_path.into(),
-        hgldd_path: args.hgldd_path.into(),
+        hgldd_path: args.hgldd_path.map(PathBuf::from),
         top_module: args.top_module,
         extra_scopes: args.extra_scopes.unwrap_or(vec![]),
         max_timesteps: args.max_timesteps,
         data_only: args.data_only.unwrap_or(false),
         group_nodes: args.hier_grouping.unwrap_or(false),
-        fir_repr: args.fir.unwrap_or(false)
+        fir_repr: args.fir.unwrap_or(false),
+        conversion_passes,
+        passing_dpdg: args.passing_dpdg.map(PathBuf::from),
+        golden_values: args.golden_values.map(PathBuf::from),
+        value_history: args.value_history
     });
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_dialog::init())
         .manage(RwLock::new(state))
-        .invoke_handler(tauri::generate_handler![get_initial_route, make_dpdg, get_n_timeslots, get_partial_graph, toggle_module, set_new_head, reset_head, open_vs_code])
+        .invoke_handler(tauri::generate_handler![get_initial_route, make_dpdg, get_conversion_passes, set_conversion_passes, get_n_timeslots, get_partial_graph, toggle_module, set_new_head, reset_head, open_vs_code, expand_firrtl_node, get_representation, switch_representation, set_value_format, get_value_formats, get_value_history, export_dot, highlight_root_causes, show_diff_overlay, highlight_x_origins, clear_highlights])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
     Ok(())